@@ -2,16 +2,28 @@
 #[argp(description = "corrodedbar - a simple X11 statusbar")]
 pub struct AppParams {
 	#[argp(option, default = "\"x11\".to_string()")]
-	#[argp(description = "Selects the backend. Available options: \"x11\", \"stdout\".")]
+	#[argp(description = "Selects the backend. Available options: \"x11\", \"x11-dock\", \"stdout\", \"i3bar\", \"lemonbar\", \"wayland\".")]
 	pub backend: String,
 
+	#[argp(option, short = 'c', arg_name = "path")]
+	#[argp(description = "Use the given config file instead of searching for one (also settable via $CORRODEDBAR_CONFIG).")]
+	pub config: Option<String>,
+
+	#[argp(switch, short = 'w')]
+	#[argp(description = "Output JSON for Waybar's `return-type: json` (stdout backend only).")]
+	pub waybar: bool,
+
 	#[argp(switch, short = 'v')]
 	#[argp(description = "Enable verbose logging during runtime.")]
 	pub verbose: bool,
 
 	#[argp(switch, short = 'n')]
 	#[argp(description = "Disable autoreload if the config file changes.")]
-	pub noautoreload: bool
+	pub noautoreload: bool,
+
+	#[argp(switch)]
+	#[argp(description = "Check the config file (and every module in it) for errors and exit. Exits with 1 if there are any.")]
+	pub check_config: bool
 }
 
 pub fn init() -> AppParams {
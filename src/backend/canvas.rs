@@ -0,0 +1,242 @@
+use crate::backend::Block;
+use crate::config::{Color, Config};
+
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+
+// A tiny software text renderer for the backends which draw the bar themselves
+
+const FALLBACK_FONTS: &[&str] = &[
+	"/usr/share/fonts/TTF/DejaVuSans.ttf",
+	"/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+	"/usr/share/fonts/dejavu/DejaVuSans.ttf",
+	"/usr/share/fonts/noto/NotoSans-Regular.ttf",
+	"/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+	"/usr/share/fonts/liberation/LiberationSans-Regular.ttf",
+	"/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf"
+];
+
+pub struct Canvas {
+	font: FontVec,
+	font_path: Option<String>
+}
+
+pub struct Style {
+	pub font_size: f32,
+	pub foreground: Color,
+	pub background: Color
+}
+
+struct Target<'a> {
+	buf: &'a mut [u8],
+	width: u32,
+	height: u32
+}
+
+pub struct Segment {
+	pub text: String,
+	pub urgent: bool,
+	pub color: Option<Color>,
+	pub background: Option<Color>
+}
+
+impl Segment {
+	pub fn plain(text: String) -> Self {
+		Segment { text, urgent: false, color: None, background: None }
+	}
+
+	// The text color and the background (if it differs from the bar's)
+	pub fn colors(&self, style: &Style) -> (Color, Option<Color>) {
+		let foreground = self.color.unwrap_or(style.foreground);
+
+		match self.urgent {
+			// Errors are drawn with inverted colors
+			true => (self.background.unwrap_or(style.background), Some(foreground)),
+			false => (foreground, self.background)
+		}
+	}
+}
+
+impl Style {
+	pub fn from_config(config: &Config) -> Self {
+		Style {
+			font_size: config.font_size,
+			foreground: config.foreground,
+			background: config.background
+		}
+	}
+}
+
+impl Default for Style {
+	// Used for showing errors before any config has been loaded
+
+	fn default() -> Self {
+		Style {
+			font_size: 14.0,
+			foreground: Color { r: 0xFF, g: 0xFF, b: 0xFF, a: 0xFF },
+			background: Color { r: 0x00, g: 0x00, b: 0x00, a: 0xFF }
+		}
+	}
+}
+
+pub fn layout(config: &Config, blocks: &[Block]) -> Vec<Segment> {
+	let mut segments = vec![Segment::plain(config.left_pad.clone())];
+
+	for (i, block) in blocks.iter().enumerate() {
+		if i > 0 {
+			segments.push(Segment::plain(config.delim.clone()));
+		}
+
+		segments.push(Segment {
+			text: block.full_text(),
+			urgent: block.urgent,
+			color: block.color,
+			background: block.background
+		});
+	}
+
+	segments.push(Segment::plain(config.right_pad.clone()));
+
+	segments
+}
+
+fn blend(pixel: &mut [u8], color: Color, coverage: f32) {
+	// The buffer is premultiplied ARGB8888 in little endian, i.e. BGRA in memory
+
+	let alpha = color.a as f32 / 255.0 * coverage;
+	let src = [color.b, color.g, color.r];
+
+	for i in 0..3 {
+		pixel[i] = (src[i] as f32 * alpha + pixel[i] as f32 * (1.0 - alpha)).round() as u8;
+	}
+
+	pixel[3] = (255.0 * alpha + pixel[3] as f32 * (1.0 - alpha)).round() as u8;
+}
+
+fn fill(target: &mut Target, x0: u32, x1: u32, color: Color) {
+	let alpha = color.a as f32 / 255.0;
+	let premultiplied = |val: u8| (val as f32 * alpha).round() as u8;
+	let pixel = [premultiplied(color.b), premultiplied(color.g), premultiplied(color.r), color.a];
+
+	for row in target.buf.chunks_exact_mut(target.width as usize * 4) {
+		for x in x0..x1.min(target.width) {
+			row[x as usize * 4..x as usize * 4 + 4].copy_from_slice(&pixel);
+		}
+	}
+}
+
+impl Canvas {
+	pub fn load(path: Option<&str>) -> Result<Self, String> {
+		let candidates = match path {
+			Some(path) => vec![path],
+			None => FALLBACK_FONTS.to_vec()
+		};
+
+		for candidate in candidates {
+			let Ok(data) = std::fs::read(candidate) else { continue };
+
+			let font = FontVec::try_from_vec(data)
+				.map_err(|e| format!("Error loading font {}: {}", candidate, e))?;
+
+			return Ok(Canvas {
+				font,
+				font_path: path.map(str::to_string)
+			})
+		}
+
+		match path {
+			Some(path) => Err(format!("Could not read font {}", path)),
+			None => Err("Could not find a usable font, please set `font` in the config".to_string())
+		}
+	}
+
+	pub fn font_path(&self) -> Option<&str> {
+		self.font_path.as_deref()
+	}
+
+	fn text_width(&self, scale: PxScale, text: &str) -> f32 {
+		let font = self.font.as_scaled(scale);
+
+		let mut width = 0.0;
+		let mut last = None;
+
+		for c in text.chars() {
+			let id = font.glyph_id(c);
+
+			if let Some(last) = last {
+				width += font.kern(last, id);
+			}
+
+			width += font.h_advance(id);
+			last = Some(id);
+		}
+
+		width
+	}
+
+	fn draw_text(&self, target: &mut Target, scale: PxScale, x: f32, text: &str, color: Color) {
+		let font = self.font.as_scaled(scale);
+
+		// Center the line vertically
+		let baseline = ((target.height as f32 - (font.ascent() - font.descent())) / 2.0 + font.ascent()).round();
+
+		let mut caret = x;
+		let mut last = None;
+
+		for c in text.chars() {
+			let id = font.glyph_id(c);
+
+			if let Some(last) = last {
+				caret += font.kern(last, id);
+			}
+
+			let glyph = id.with_scale_and_position(scale, ab_glyph::point(caret, baseline));
+
+			caret += font.h_advance(id);
+			last = Some(id);
+
+			let Some(outline) = self.font.outline_glyph(glyph) else { continue };
+
+			let bounds = outline.px_bounds();
+
+			outline.draw(|gx, gy, coverage| {
+				let px = bounds.min.x as i32 + gx as i32;
+				let py = bounds.min.y as i32 + gy as i32;
+
+				if px < 0 || py < 0 || px >= target.width as i32 || py >= target.height as i32 {
+					return
+				}
+
+				let offset = (py as usize * target.width as usize + px as usize) * 4;
+
+				blend(&mut target.buf[offset..offset + 4], color, coverage);
+			});
+		}
+	}
+
+	pub fn draw(&self, buf: &mut [u8], width: u32, height: u32, style: &Style, segments: &[Segment]) {
+		let scale = PxScale::from(style.font_size);
+
+		let mut target = Target { buf, width, height };
+
+		fill(&mut target, 0, width, style.background);
+
+		// The modules are aligned to the right edge, like in most status bars
+
+		let widths: Vec<f32> = segments.iter().map(|seg| self.text_width(scale, &seg.text)).collect();
+		let total: f32 = widths.iter().sum();
+
+		let mut x = (width as f32 - total).max(0.0);
+
+		for (segment, seg_width) in segments.iter().zip(widths) {
+			let (foreground, background) = segment.colors(style);
+
+			if let Some(background) = background {
+				fill(&mut target, x as u32, (x + seg_width).ceil() as u32, background);
+			}
+
+			self.draw_text(&mut target, scale, x, &segment.text, foreground);
+
+			x += seg_width;
+		}
+	}
+}
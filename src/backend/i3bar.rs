@@ -0,0 +1,151 @@
+use crate::backend::{Backend, Block};
+use crate::config::{Color, Config};
+use crate::modules::ClickEvent;
+use crate::waker;
+
+use itertools::Itertools;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+// https://i3wm.org/docs/i3bar-protocol.html
+
+#[derive(serde::Serialize)]
+struct Header {
+	version: u32,
+	click_events: bool
+}
+
+#[derive(serde::Serialize)]
+struct I3barBlock<'a> {
+	name: &'a str,
+	instance: &'a str,
+	full_text: String,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	short_text: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	color: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	background: Option<String>,
+
+	urgent: bool,
+	separator: bool
+}
+
+fn hex(color: Color) -> String {
+	match color.a {
+		0xFF => format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+		_ => format!("#{:02x}{:02x}{:02x}{:02x}", color.r, color.g, color.b, color.a)
+	}
+}
+
+#[derive(Default)]
+pub struct I3barBackend {
+	started: bool,
+	events: Option<Receiver<ClickEvent>>
+}
+
+fn read_click_events(tx: mpsc::Sender<ClickEvent>) {
+	// The click events are sent as an infinite array as well, one event per line
+
+	for line in std::io::stdin().lock().lines() {
+		let Ok(line) = line else { break };
+
+		let line = line.trim().trim_start_matches(['[', ',']);
+
+		if line.is_empty() { continue }
+
+		match serde_json::from_str::<ClickEvent>(line) {
+			Ok(event) => {
+				if tx.send(event).is_err() { break }
+				waker::wake();
+			},
+			Err(err) => eprintln!("Invalid click event: {}", err)
+		}
+	}
+}
+
+impl I3barBackend {
+	fn next_event(&mut self) -> Option<ClickEvent> {
+		match self.events.as_ref()?.try_recv() {
+			Ok(event) => Some(event),
+			Err(TryRecvError::Empty) => None,
+			Err(TryRecvError::Disconnected) => {
+				// stdin was closed, there will be no more clicks
+
+				self.events = None;
+				None
+			}
+		}
+	}
+
+	fn write_blocks(&mut self, blocks: &[I3barBlock]) {
+		if !self.started {
+			// The body is an infinite array, so every status line after the first one
+			// has to be prefixed with a comma.
+
+			println!("{}", serde_json::to_string(&Header { version: 1, click_events: true }).unwrap());
+			println!("[");
+			self.started = true;
+
+			let (tx, rx) = mpsc::channel();
+
+			std::thread::spawn(move || read_click_events(tx));
+
+			self.events = Some(rx);
+		} else {
+			print!(",");
+		}
+
+		println!("[{}]", blocks.iter()
+			.map(|block| serde_json::to_string(block).unwrap())
+			.join(","));
+	}
+}
+
+impl Backend for I3barBackend {
+	fn output(&mut self, _config: &Config, blocks: &[Block]) {
+		let blocks = blocks.iter()
+			.map(|block| I3barBlock {
+				name: block.name,
+				instance: &block.instance,
+				full_text: block.full_text(),
+				short_text: block.short_text(),
+				color: block.color.map(hex),
+				background: block.background.map(hex),
+				urgent: block.urgent,
+				separator: true
+			})
+			.collect_vec();
+
+		self.write_blocks(&blocks);
+	}
+
+	fn output_error(&mut self, err: &str) {
+		self.write_blocks(&[I3barBlock {
+			name: "corrodedbar",
+			instance: "",
+			full_text: err.to_string(),
+			short_text: None,
+			color: None,
+			background: None,
+			urgent: true,
+			separator: true
+		}]);
+	}
+
+	fn wait(&mut self, timeout: Duration) -> Option<ClickEvent> {
+		// The reader thread wakes us up whenever there is a new event
+
+		if self.events.is_some() && let Some(event) = self.next_event() {
+			return Some(event)
+		}
+
+		waker::poll(None, timeout);
+
+		self.next_event()
+	}
+}
@@ -0,0 +1,66 @@
+use crate::backend::{Backend, Block};
+use crate::config::{Align, Color, Config};
+
+use itertools::Itertools;
+
+// https://github.com/LemonBoy/bar#formatting (also understood by polybar)
+
+pub struct LemonbarBackend {}
+
+fn escape(text: &str) -> String {
+	text.replace('%', "%%")
+}
+
+fn hex(color: Color) -> String {
+	match color.a {
+		0xFF => format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+		_ => format!("#{:02x}{:02x}{:02x}{:02x}", color.a, color.r, color.g, color.b)
+	}
+}
+
+fn format_block(block: &Block) -> String {
+	let mut out = escape(&block.full_text());
+
+	if block.urgent {
+		// Swap the foreground and background colors
+		out = format!("%{{R}}{}%{{R}}", out);
+	}
+
+	if let Some(cmd) = block.on_click {
+		out = format!("%{{A:{}:}}{}%{{A}}", cmd.replace(':', "\\:"), out);
+	}
+
+	if let Some(color) = block.background {
+		out = format!("%{{B{}}}{}%{{B-}}", hex(color), out);
+	}
+
+	if let Some(color) = block.color {
+		out = format!("%{{F{}}}{}%{{F-}}", hex(color), out);
+	}
+
+	out
+}
+
+fn format_line(config: &Config, blocks: &[Block]) -> String {
+	let section = |align: Align| blocks.iter()
+		.filter(|block| block.align == align)
+		.map(format_block)
+		.join(&escape(&config.delim));
+
+	format!("%{{l}}{}{}%{{c}}{}%{{r}}{}{}",
+		escape(&config.left_pad),
+		section(Align::Left),
+		section(Align::Center),
+		section(Align::Right),
+		escape(&config.right_pad))
+}
+
+impl Backend for LemonbarBackend {
+	fn output(&mut self, config: &Config, blocks: &[Block]) {
+		println!("{}", format_line(config, blocks));
+	}
+
+	fn output_error(&mut self, err: &str) {
+		println!("%{{r}}{}", escape(err));
+	}
+}
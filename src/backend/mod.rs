@@ -1,7 +1,67 @@
 pub mod x11;
 pub mod stdout;
+pub mod i3bar;
+pub mod wayland;
+pub mod x11dock;
+pub mod lemonbar;
+mod canvas;
+
+use crate::config::{Align, Color, Config};
+use crate::modules::ClickEvent;
+use crate::waker;
+
+use itertools::Itertools;
+use std::time::Duration;
+
+pub struct Block<'a> {
+	pub name: &'a str,
+	pub instance: String,
+	pub icon: Option<&'a str>,
+	pub text: &'a str,
+	pub short_text: Option<&'a str>,
+	pub tooltip: Option<&'a str>,
+	pub urgent: bool,
+	pub classes: &'a [String],
+	pub value: Option<f64>,
+	pub is_percentage: bool,
+	pub color: Option<Color>,
+	pub background: Option<Color>,
+	pub align: Align,
+	pub on_click: Option<&'a str>
+}
+
+impl Block<'_> {
+	fn with_icon(&self, text: &str) -> String {
+		match self.icon {
+			Some(icon) => format!("{} {}", icon, text),
+			None => text.to_string()
+		}
+	}
+
+	pub fn full_text(&self) -> String {
+		self.with_icon(self.text)
+	}
+
+	pub fn short_text(&self) -> Option<String> {
+		self.short_text.map(|text| self.with_icon(text))
+	}
+}
 
 pub trait Backend {
-	fn output(val: &str);
+	fn output(&mut self, config: &Config, blocks: &[Block]);
+	fn output_error(&mut self, err: &str);
+
+	// Sleeps for the given amount of time, or until something wakes up the scheduler
+	// (see `waker`). Backends which support mouse input also return early when the
+	// user clicks on one of the blocks.
+	fn wait(&mut self, timeout: Duration) -> Option<ClickEvent> {
+		waker::poll(None, timeout);
+		None
+	}
 }
 
+pub fn join_blocks(config: &Config, blocks: &[Block]) -> String {
+	// The classic single-line layout, used by backends which can only display plain text
+
+	config.left_pad.clone() + &blocks.iter().map(Block::full_text).join(&config.delim) + &config.right_pad
+}
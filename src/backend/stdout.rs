@@ -1,10 +1,81 @@
-use crate::backend::Backend;
+use crate::backend::{self, Backend, Block};
+use crate::config::Config;
 
-pub struct StdoutBackend {}
+use itertools::Itertools;
 
-impl Backend for StdoutBackend {
-	fn output(val: &str) {
-		println!("{}", val); // As simple as it gets.
+pub struct StdoutBackend {
+	pub waybar: bool
+}
+
+// https://man.archlinux.org/man/waybar-custom.5 (`return-type: json`)
+
+#[derive(serde::Serialize)]
+struct WaybarOutput {
+	text: String,
+	tooltip: String,
+	class: Vec<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	percentage: Option<u32>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	alt: Option<String>
+}
+
+fn escape_markup(val: &str) -> String {
+	// Waybar interprets the text as Pango markup
+
+	val.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn waybar_output(config: &Config, blocks: &[Block]) -> WaybarOutput {
+	let class = blocks.iter()
+		.flat_map(|block| {
+			let error = block.urgent.then(|| format!("{}-error", block.name));
+
+			block.classes.iter()
+				.map(|class| format!("{}-{}", block.name, class))
+				.chain(error)
+		})
+		.unique()
+		.collect_vec();
+
+	// Waybar only supports a single percentage, so take the first module which has one
+	// (other values, like temperatures or rates, would just end up clamped)
+
+	let gauge = blocks.iter().find(|block| block.is_percentage && block.value.is_some());
+
+	WaybarOutput {
+		text: escape_markup(&backend::join_blocks(config, blocks)),
+		tooltip: escape_markup(&blocks.iter()
+			.map(|block| format!("{}: {}", block.name, block.tooltip.map_or_else(|| block.full_text(), str::to_string)))
+			.join("\n")),
+		class,
+		percentage: gauge.and_then(|block| block.value).map(|val| val.round().clamp(0.0, 100.0) as u32),
+		alt: gauge.map(|block| block.classes.first().map_or(block.name, |class| class.as_str()).to_string())
 	}
 }
 
+impl Backend for StdoutBackend {
+	fn output(&mut self, config: &Config, blocks: &[Block]) {
+		if self.waybar {
+			println!("{}", serde_json::to_string(&waybar_output(config, blocks)).unwrap());
+		} else {
+			println!("{}", backend::join_blocks(config, blocks)); // As simple as it gets.
+		}
+	}
+
+	fn output_error(&mut self, err: &str) {
+		if self.waybar {
+			println!("{}", serde_json::to_string(&WaybarOutput {
+				text: escape_markup(err),
+				tooltip: escape_markup(err),
+				class: vec!["error".to_string()],
+				percentage: None,
+				alt: None
+			}).unwrap());
+		} else {
+			println!("{}", err);
+		}
+	}
+}
@@ -0,0 +1,292 @@
+use crate::backend::{Backend, Block};
+use crate::backend::canvas::{self, Canvas, Segment, Style};
+use crate::config::{BarPosition, Config};
+use crate::modules::ClickEvent;
+use crate::waker;
+
+use smithay_client_toolkit::{
+	compositor::{CompositorHandler, CompositorState},
+	delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
+	output::{OutputHandler, OutputState},
+	registry::{ProvidesRegistryState, RegistryState},
+	registry_handlers,
+	shell::{
+		wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
+		WaylandSurface
+	},
+	shm::{slot::SlotPool, Shm, ShmHandler}
+};
+use smithay_client_toolkit::reexports::client::{
+	globals::registry_queue_init,
+	protocol::{wl_output, wl_shm, wl_surface},
+	Connection, EventQueue, QueueHandle
+};
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+// Draws the bar as a wlr-layer-shell surface, no Waybar needed
+
+#[derive(Clone, Copy, PartialEq)]
+struct SurfaceSettings {
+	position: BarPosition,
+	height: u32
+}
+
+struct Bar {
+	registry_state: RegistryState,
+	output_state: OutputState,
+	compositor: CompositorState,
+	layer_shell: LayerShell,
+	shm: Shm,
+	pool: SlotPool,
+
+	surface: Option<LayerSurface>,
+	settings: Option<SurfaceSettings>,
+	configured: bool,
+	width: u32,
+	height: u32,
+
+	canvas: Option<Canvas>,
+	style: Style,
+	segments: Vec<Segment>
+}
+
+pub struct WaylandBackend {
+	event_queue: EventQueue<Bar>,
+	bar: Bar
+}
+
+impl WaylandBackend {
+	pub fn new() -> Result<Self, String> {
+		let conn = Connection::connect_to_env()
+			.map_err(|e| format!("Could not connect to the Wayland compositor: {}", e))?;
+
+		let (globals, event_queue) = registry_queue_init(&conn)
+			.map_err(|e| format!("Wayland registry error: {}", e))?;
+
+		let qh = event_queue.handle();
+
+		let compositor = CompositorState::bind(&globals, &qh)
+			.map_err(|e| format!("wl_compositor is not available: {}", e))?;
+
+		let layer_shell = LayerShell::bind(&globals, &qh)
+			.map_err(|e| format!("The compositor does not support wlr-layer-shell: {}", e))?;
+
+		let shm = Shm::bind(&globals, &qh)
+			.map_err(|e| format!("wl_shm is not available: {}", e))?;
+
+		let pool = SlotPool::new(4096, &shm)
+			.map_err(|e| format!("Error creating shared memory pool: {}", e))?;
+
+		Ok(WaylandBackend {
+			bar: Bar {
+				registry_state: RegistryState::new(&globals),
+				output_state: OutputState::new(&globals, &qh),
+				compositor,
+				layer_shell,
+				shm,
+				pool,
+				surface: None,
+				settings: None,
+				configured: false,
+				width: 0,
+				height: 0,
+				canvas: None,
+				style: Style::default(),
+				segments: Vec::new()
+			},
+			event_queue
+		})
+	}
+
+	fn update(&mut self, settings: Option<SurfaceSettings>) {
+		let qh = self.event_queue.handle();
+
+		// Recreate the surface if the config changed its placement (or if there is none yet)
+
+		let settings = settings.or(self.bar.settings).unwrap_or(SurfaceSettings {
+			position: BarPosition::default(),
+			height: 24
+		});
+
+		if self.bar.surface.is_none() || self.bar.settings != Some(settings) {
+			self.bar.create_surface(&qh, settings);
+		}
+
+		self.bar.draw();
+
+		if let Err(err) = self.event_queue.flush() {
+			eprintln!("Wayland error: {}", err);
+		}
+	}
+}
+
+impl Bar {
+	fn create_surface(&mut self, qh: &QueueHandle<Self>, settings: SurfaceSettings) {
+		let surface = self.compositor.create_surface(qh);
+
+		let layer = self.layer_shell.create_layer_surface(qh, surface, Layer::Top, Some("corrodedbar"), None);
+
+		let edge = match settings.position {
+			BarPosition::Top => Anchor::TOP,
+			BarPosition::Bottom => Anchor::BOTTOM
+		};
+
+		layer.set_anchor(edge | Anchor::LEFT | Anchor::RIGHT);
+		layer.set_size(0, settings.height);
+		layer.set_exclusive_zone(settings.height as i32);
+		layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+		// The compositor will answer with a configure event, only then can we draw
+		layer.commit();
+
+		self.surface = Some(layer);
+		self.settings = Some(settings);
+		self.configured = false;
+		self.height = settings.height;
+	}
+
+	fn draw(&mut self) {
+		let (Some(surface), Some(canvas)) = (&self.surface, &self.canvas) else { return };
+
+		if !self.configured || self.width == 0 || self.height == 0 {
+			return
+		}
+
+		let stride = self.width as i32 * 4;
+
+		let (buffer, pixels) = match self.pool.create_buffer(self.width as i32, self.height as i32, stride, wl_shm::Format::Argb8888) {
+			Ok(val) => val,
+			Err(err) => {
+				eprintln!("Error allocating Wayland buffer: {}", err);
+				return
+			}
+		};
+
+		canvas.draw(pixels, self.width, self.height, &self.style, &self.segments);
+
+		surface.wl_surface().damage_buffer(0, 0, self.width as i32, self.height as i32);
+
+		if let Err(err) = buffer.attach_to(surface.wl_surface()) {
+			eprintln!("Error attaching Wayland buffer: {}", err);
+			return
+		}
+
+		surface.commit();
+	}
+}
+
+impl Backend for WaylandBackend {
+	fn output(&mut self, config: &Config, blocks: &[Block]) {
+		let font_changed = self.bar.canvas.as_ref().is_none_or(|canvas| canvas.font_path() != config.font.as_deref());
+
+		if font_changed {
+			match Canvas::load(config.font.as_deref()) {
+				Ok(canvas) => self.bar.canvas = Some(canvas),
+				Err(err) => eprintln!("{}", err)
+			}
+		}
+
+		self.bar.style = Style::from_config(config);
+		self.bar.segments = canvas::layout(config, blocks);
+
+		self.update(Some(SurfaceSettings {
+			position: config.position,
+			height: config.height
+		}));
+	}
+
+	fn output_error(&mut self, err: &str) {
+		if self.bar.canvas.is_none() {
+			self.bar.canvas = Canvas::load(None).ok();
+		}
+
+		self.bar.segments = vec![Segment::plain(format!(" {} ", err))];
+
+		self.update(None);
+	}
+
+	fn wait(&mut self, timeout: Duration) -> Option<ClickEvent> {
+		let result = (|| {
+			self.event_queue.dispatch_pending(&mut self.bar)?;
+			self.event_queue.flush()?;
+
+			if let Some(guard) = self.event_queue.prepare_read()
+				&& waker::poll(Some(guard.connection_fd().as_raw_fd()), timeout)
+			{
+				guard.read()?;
+			}
+
+			self.event_queue.dispatch_pending(&mut self.bar)?;
+
+			Ok::<(), Box<dyn std::error::Error>>(())
+		})();
+
+		if let Err(err) = result {
+			// Without the compositor, there is nothing left to draw on
+
+			eprintln!("Lost connection to the Wayland compositor: {}", err);
+			std::process::exit(1);
+		}
+
+		None
+	}
+}
+
+impl CompositorHandler for Bar {
+	fn scale_factor_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: i32) {}
+	fn transform_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: wl_output::Transform) {}
+	fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+	fn surface_enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: &wl_output::WlOutput) {}
+	fn surface_leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: &wl_output::WlOutput) {}
+}
+
+impl OutputHandler for Bar {
+	fn output_state(&mut self) -> &mut OutputState {
+		&mut self.output_state
+	}
+
+	fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+	fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+	fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl LayerShellHandler for Bar {
+	fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+		// E.g. the output went away, a new surface will be created on the next update
+
+		self.surface = None;
+		self.configured = false;
+	}
+
+	fn configure(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface, configure: LayerSurfaceConfigure, _: u32) {
+		self.width = configure.new_size.0;
+
+		if configure.new_size.1 != 0 {
+			self.height = configure.new_size.1;
+		}
+
+		self.configured = true;
+		self.draw();
+	}
+}
+
+impl ShmHandler for Bar {
+	fn shm_state(&mut self) -> &mut Shm {
+		&mut self.shm
+	}
+}
+
+impl ProvidesRegistryState for Bar {
+	fn registry(&mut self) -> &mut RegistryState {
+		&mut self.registry_state
+	}
+
+	registry_handlers![OutputState];
+}
+
+delegate_compositor!(Bar);
+delegate_output!(Bar);
+delegate_shm!(Bar);
+delegate_layer!(Bar);
+delegate_registry!(Bar);
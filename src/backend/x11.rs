@@ -1,23 +1,195 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use x11::xlib;
 
-use crate::backend::Backend;
+use crate::backend::{self, Backend, Block};
+use crate::config::Config;
 
-pub struct X11Backend {}
+struct X11Connection {
+	dpy: *mut xlib::Display,
+	root_win: xlib::Window,
+	net_wm_name: xlib::Atom,
+	utf8_string: xlib::Atom,
+	// Boxed, Xlib holds a pointer to it in the IO error exit handler
+	broken: Box<AtomicBool>
+}
 
-impl Backend for X11Backend {
-	fn output(val: &str) {
+#[derive(Default)]
+pub struct X11Backend {
+	conn: Option<X11Connection>,
+	last_error: Option<String>
+}
+
+unsafe extern "C" fn handle_x_error(_dpy: *mut xlib::Display, event: *mut xlib::XErrorEvent) -> i32 {
+	// The default handler would terminate the whole process
+
+	unsafe {
+		eprintln!("X11 error: request {} failed with code {}", (*event).request_code, (*event).error_code);
+	}
+
+	0
+}
+
+unsafe extern "C" fn handle_x_io_error(_dpy: *mut xlib::Display) -> i32 {
+	eprintln!("X11 IO error, the connection to the server is broken");
+
+	0
+}
+
+unsafe extern "C" fn handle_x_io_exit(_dpy: *mut xlib::Display, broken: *mut libc::c_void) {
+	// The default exit handler would terminate the whole process, just mark
+	// the connection as dead so that it gets replaced on the next update
+
+	unsafe {
+		(*(broken as *const AtomicBool)).store(true, Ordering::Relaxed);
+	}
+}
+
+// Not bound by the x11 crate, available since libX11 1.7
+unsafe extern "C" {
+	fn XSetIOErrorExitHandler(dpy: *mut xlib::Display,
+		handler: Option<unsafe extern "C" fn(*mut xlib::Display, *mut libc::c_void)>,
+		user_data: *mut libc::c_void);
+}
+
+impl X11Connection {
+	fn open() -> Result<Self, String> {
 		unsafe {
-			let c_str = std::ffi::CString::new(val).unwrap();
+			xlib::XSetErrorHandler(Some(handle_x_error));
+			xlib::XSetIOErrorHandler(Some(handle_x_io_error));
 
 			let dpy = xlib::XOpenDisplay(std::ptr::null());
 
+			if dpy.is_null() {
+				Err("Could not open X display".to_string())?
+			}
+
+			let broken = Box::new(AtomicBool::new(false));
+
+			XSetIOErrorExitHandler(dpy, Some(handle_x_io_exit), &*broken as *const AtomicBool as *mut libc::c_void);
+
 			let screen = xlib::XDefaultScreen(dpy);
-			let root_win = xlib::XRootWindow(dpy, screen);
 
-			xlib::XStoreName(dpy, root_win, c_str.as_ptr());
+			Ok(X11Connection {
+				dpy,
+				root_win: xlib::XRootWindow(dpy, screen),
+				net_wm_name: xlib::XInternAtom(dpy, c"_NET_WM_NAME".as_ptr(), xlib::False),
+				utf8_string: xlib::XInternAtom(dpy, c"UTF8_STRING".as_ptr(), xlib::False),
+				broken
+			})
+		}
+	}
+
+	fn is_alive(&self) -> bool {
+		// Catch a hung up socket early, the IO error handlers cover the rest
+
+		if self.broken.load(Ordering::Relaxed) {
+			return false
+		}
+
+		unsafe {
+			let fd = xlib::XConnectionNumber(self.dpy);
+
+			let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+
+			if libc::poll(&mut pollfd, 1, 0) < 0 {
+				return false
+			}
+
+			if pollfd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 {
+				return false
+			}
+
+			if pollfd.revents & libc::POLLIN != 0 {
+				let mut buf = 0u8;
+
+				let read = libc::recv(fd, &mut buf as *mut u8 as *mut libc::c_void, 1, libc::MSG_PEEK | libc::MSG_DONTWAIT);
+
+				if read == 0 {
+					return false
+				}
+
+				// Let Xlib process whatever the server sent us (usually errors)
+				xlib::XSync(self.dpy, xlib::False);
+			}
+		}
+
+		!self.broken.load(Ordering::Relaxed)
+	}
+
+	fn set_root_name(&self, val: &str) -> Result<(), String> {
+		let c_str = std::ffi::CString::new(val.replace('\0', ""))
+			.map_err(|e| format!("Invalid root window name: {}", e))?;
+
+		let len = i32::try_from(c_str.as_bytes().len())
+			.map_err(|_| "Root window name too long".to_string())?;
+
+		unsafe {
+			// WM_NAME for dwm & co., _NET_WM_NAME for everything which expects proper UTF-8
+
+			xlib::XStoreName(self.dpy, self.root_win, c_str.as_ptr());
+
+			xlib::XChangeProperty(self.dpy, self.root_win, self.net_wm_name, self.utf8_string, 8,
+				xlib::PropModeReplace, c_str.as_ptr() as *const u8, len);
+
+			xlib::XFlush(self.dpy);
+		}
+
+		if self.broken.load(Ordering::Relaxed) {
+			Err("Lost connection to the X server".to_string())?
+		}
+
+		Ok(())
+	}
+}
+
+impl Drop for X11Connection {
+	fn drop(&mut self) {
+		// Safe even on a broken connection, Xlib skips talking to the server then
+
+		unsafe {
+			xlib::XCloseDisplay(self.dpy);
+		}
+	}
+}
+
+impl X11Backend {
+	fn connection(&mut self) -> Result<&X11Connection, String> {
+		if self.conn.take_if(|conn| !conn.is_alive()).is_some() {
+			eprintln!("Lost connection to the X server, reconnecting.");
+		}
+
+		if self.conn.is_none() {
+			self.conn = Some(X11Connection::open()?);
+		}
+
+		Ok(self.conn.as_ref().unwrap())
+	}
+
+	fn set_root_name(&mut self, val: &str) {
+		let result = self.connection().and_then(|conn| conn.set_root_name(val));
+
+		// Only report each problem once, the bar may be updated very often
 
-			xlib::XCloseDisplay(dpy);
+		match result {
+			Ok(()) => self.last_error = None,
+			Err(err) => {
+				if self.last_error.as_ref() != Some(&err) {
+					eprintln!("X11 backend error: {}", err);
+				}
+
+				self.last_error = Some(err);
+			}
 		}
 	}
 }
 
+impl Backend for X11Backend {
+	fn output(&mut self, config: &Config, blocks: &[Block]) {
+		self.set_root_name(&backend::join_blocks(config, blocks));
+	}
+
+	fn output_error(&mut self, err: &str) {
+		self.set_root_name(err);
+	}
+}
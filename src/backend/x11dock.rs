@@ -0,0 +1,492 @@
+use x11::{xft, xlib, xrandr, xrender};
+
+use crate::backend::{Backend, Block};
+use crate::backend::canvas::{self, Segment, Style};
+use crate::config::{BarPosition, Color, Config};
+use crate::modules::ClickEvent;
+use crate::waker;
+
+use std::ffi::CString;
+use std::time::Duration;
+
+// Creates its own dock window on every monitor, for WMs which do not display the root window name
+
+#[derive(Clone, Copy, PartialEq)]
+struct DockSettings {
+	position: BarPosition,
+	height: u32
+}
+
+struct Monitor {
+	x: i32,
+	y: i32,
+	width: u32,
+	height: u32
+}
+
+struct DockWindow {
+	win: xlib::Window,
+	pixmap: xlib::Pixmap,
+	draw: *mut xft::XftDraw,
+	width: u32,
+	height: u32
+}
+
+enum DockFont {
+	Xft(*mut xft::XftFont),
+	Core(*mut xlib::XFontStruct)
+}
+
+struct Atoms {
+	window_type: xlib::Atom,
+	window_type_dock: xlib::Atom,
+	state: xlib::Atom,
+	state_sticky: xlib::Atom,
+	state_above: xlib::Atom,
+	desktop: xlib::Atom,
+	strut: xlib::Atom,
+	strut_partial: xlib::Atom
+}
+
+pub struct X11DockBackend {
+	dpy: *mut xlib::Display,
+	screen: i32,
+	root: xlib::Window,
+	gc: xlib::GC,
+	atoms: Atoms,
+	randr_event_base: Option<i32>,
+
+	font: Option<DockFont>,
+	font_spec: Option<(Option<String>, f32)>,
+
+	windows: Vec<DockWindow>,
+	settings: Option<DockSettings>,
+	style: Style,
+	segments: Vec<Segment>
+}
+
+fn intern(dpy: *mut xlib::Display, name: &std::ffi::CStr) -> xlib::Atom {
+	unsafe { xlib::XInternAtom(dpy, name.as_ptr(), xlib::False) }
+}
+
+fn to_latin1(text: &str) -> Vec<u8> {
+	// Core fonts can't do Unicode
+
+	text.chars().map(|c| u8::try_from(c as u32).unwrap_or(b'?')).collect()
+}
+
+impl X11DockBackend {
+	pub fn new() -> Result<Self, String> {
+		unsafe {
+			let dpy = xlib::XOpenDisplay(std::ptr::null());
+
+			if dpy.is_null() {
+				Err("Could not open X display".to_string())?
+			}
+
+			let screen = xlib::XDefaultScreen(dpy);
+			let root = xlib::XRootWindow(dpy, screen);
+
+			let atoms = Atoms {
+				window_type: intern(dpy, c"_NET_WM_WINDOW_TYPE"),
+				window_type_dock: intern(dpy, c"_NET_WM_WINDOW_TYPE_DOCK"),
+				state: intern(dpy, c"_NET_WM_STATE"),
+				state_sticky: intern(dpy, c"_NET_WM_STATE_STICKY"),
+				state_above: intern(dpy, c"_NET_WM_STATE_ABOVE"),
+				desktop: intern(dpy, c"_NET_WM_DESKTOP"),
+				strut: intern(dpy, c"_NET_WM_STRUT"),
+				strut_partial: intern(dpy, c"_NET_WM_STRUT_PARTIAL")
+			};
+
+			// Get notified when monitors are (un)plugged or rearranged
+
+			let mut event_base = 0;
+			let mut error_base = 0;
+
+			let randr_event_base = (xrandr::XRRQueryExtension(dpy, &mut event_base, &mut error_base) != 0).then(|| {
+				xrandr::XRRSelectInput(dpy, root, xrandr::RRScreenChangeNotifyMask);
+				event_base
+			});
+
+			Ok(X11DockBackend {
+				dpy,
+				screen,
+				root,
+				gc: xlib::XCreateGC(dpy, root, 0, std::ptr::null_mut()),
+				atoms,
+				randr_event_base,
+				font: None,
+				font_spec: None,
+				windows: Vec::new(),
+				settings: None,
+				style: Style::default(),
+				segments: Vec::new()
+			})
+		}
+	}
+
+	fn monitors(&self) -> Vec<Monitor> {
+		let mut monitors = Vec::new();
+
+		unsafe {
+			if self.randr_event_base.is_some() {
+				let mut count = 0;
+
+				let info = xrandr::XRRGetMonitors(self.dpy, self.root, xlib::True, &mut count);
+
+				if !info.is_null() {
+					for m in std::slice::from_raw_parts(info, count as usize) {
+						monitors.push(Monitor { x: m.x, y: m.y, width: m.width as u32, height: m.height as u32 });
+					}
+
+					xrandr::XRRFreeMonitors(info);
+				}
+			}
+
+			if monitors.is_empty() {
+				monitors.push(Monitor {
+					x: 0,
+					y: 0,
+					width: xlib::XDisplayWidth(self.dpy, self.screen) as u32,
+					height: xlib::XDisplayHeight(self.dpy, self.screen) as u32
+				});
+			}
+		}
+
+		monitors
+	}
+
+	fn load_font(&mut self, font: Option<&str>, size: f32) {
+		if self.font_spec.as_ref().is_some_and(|(f, s)| f.as_deref() == font && *s == size) {
+			return
+		}
+
+		self.free_font();
+
+		// A path is used as a font file, anything else as a fontconfig pattern (e.g. "monospace:bold")
+
+		let pattern = match font {
+			Some(path) if path.starts_with('/') => format!(":file={}:pixelsize={}", path, size),
+			Some(name) => format!("{}:pixelsize={}", name, size),
+			None => format!("sans:pixelsize={}", size)
+		};
+
+		unsafe {
+			let xft_font = CString::new(pattern)
+				.map(|pattern| xft::XftFontOpenName(self.dpy, self.screen, pattern.as_ptr()))
+				.unwrap_or(std::ptr::null_mut());
+
+			self.font = if !xft_font.is_null() {
+				Some(DockFont::Xft(xft_font))
+			} else {
+				eprintln!("Could not load font with Xft, falling back to the core `fixed` font.");
+
+				let core_font = xlib::XLoadQueryFont(self.dpy, c"fixed".as_ptr());
+
+				(!core_font.is_null()).then_some(DockFont::Core(core_font))
+			};
+		}
+
+		self.font_spec = Some((font.map(str::to_string), size));
+	}
+
+	fn free_font(&mut self) {
+		unsafe {
+			match self.font.take() {
+				Some(DockFont::Xft(font)) => xft::XftFontClose(self.dpy, font),
+				Some(DockFont::Core(font)) => { xlib::XFreeFont(self.dpy, font); },
+				None => {}
+			}
+		}
+
+		self.font_spec = None;
+	}
+
+	fn set_cardinals(&self, win: xlib::Window, property: xlib::Atom, kind: xlib::Atom, values: &[u64]) {
+		// Format 32 properties are passed as longs, no matter their actual size
+
+		let values: Vec<std::ffi::c_long> = values.iter().map(|x| *x as std::ffi::c_long).collect();
+
+		unsafe {
+			xlib::XChangeProperty(self.dpy, win, property, kind, 32, xlib::PropModeReplace,
+				values.as_ptr() as *const u8, values.len() as i32);
+		}
+	}
+
+	fn create_windows(&mut self, settings: DockSettings) {
+		self.destroy_windows();
+
+		unsafe {
+			let screen_height = xlib::XDisplayHeight(self.dpy, self.screen) as u64;
+			let depth = xlib::XDefaultDepth(self.dpy, self.screen);
+			let visual = xlib::XDefaultVisual(self.dpy, self.screen);
+			let colormap = xlib::XDefaultColormap(self.dpy, self.screen);
+
+			for monitor in self.monitors() {
+				let height = settings.height.min(monitor.height);
+
+				let y = match settings.position {
+					BarPosition::Top => monitor.y,
+					BarPosition::Bottom => monitor.y + (monitor.height - height) as i32
+				};
+
+				let mut attrs: xlib::XSetWindowAttributes = std::mem::zeroed();
+				attrs.event_mask = xlib::ExposureMask;
+
+				let win = xlib::XCreateWindow(self.dpy, self.root, monitor.x, y, monitor.width, height, 0,
+					xlib::CopyFromParent, xlib::InputOutput as u32, std::ptr::null_mut(), xlib::CWEventMask, &mut attrs);
+
+				// Tell the WM that this is a dock which should reserve space on its monitor
+
+				self.set_cardinals(win, self.atoms.window_type, xlib::XA_ATOM, &[self.atoms.window_type_dock]);
+				self.set_cardinals(win, self.atoms.state, xlib::XA_ATOM, &[self.atoms.state_sticky, self.atoms.state_above]);
+				self.set_cardinals(win, self.atoms.desktop, xlib::XA_CARDINAL, &[0xFFFFFFFF]);
+
+				let x_start = monitor.x as u64;
+				let x_end = x_start + monitor.width as u64 - 1;
+
+				let strut: [u64; 12] = match settings.position {
+					BarPosition::Top => [0, 0, y as u64 + height as u64, 0, 0, 0, 0, 0, x_start, x_end, 0, 0],
+					BarPosition::Bottom => [0, 0, 0, screen_height - y as u64, 0, 0, 0, 0, 0, 0, x_start, x_end]
+				};
+
+				self.set_cardinals(win, self.atoms.strut, xlib::XA_CARDINAL, &strut[0..4]);
+				self.set_cardinals(win, self.atoms.strut_partial, xlib::XA_CARDINAL, &strut);
+
+				xlib::XStoreName(self.dpy, win, c"corrodedbar".as_ptr());
+
+				let mut class_hint = xlib::XClassHint {
+					res_name: c"corrodedbar".as_ptr() as *mut _,
+					res_class: c"corrodedbar".as_ptr() as *mut _
+				};
+
+				xlib::XSetClassHint(self.dpy, win, &mut class_hint);
+
+				xlib::XMapWindow(self.dpy, win);
+
+				// Everything is drawn into a pixmap first to avoid flickering
+
+				let pixmap = xlib::XCreatePixmap(self.dpy, win, monitor.width, height, depth as u32);
+				let draw = xft::XftDrawCreate(self.dpy, pixmap, visual, colormap);
+
+				self.windows.push(DockWindow { win, pixmap, draw, width: monitor.width, height });
+			}
+		}
+
+		self.settings = Some(settings);
+	}
+
+	fn destroy_windows(&mut self) {
+		unsafe {
+			for window in self.windows.drain(..) {
+				xft::XftDrawDestroy(window.draw);
+				xlib::XFreePixmap(self.dpy, window.pixmap);
+				xlib::XDestroyWindow(self.dpy, window.win);
+			}
+		}
+	}
+
+	fn alloc_color(&self, color: Color) -> xft::XftColor {
+		let render_color = xrender::XRenderColor {
+			red: color.r as u16 * 0x101,
+			green: color.g as u16 * 0x101,
+			blue: color.b as u16 * 0x101,
+			alpha: color.a as u16 * 0x101
+		};
+
+		unsafe {
+			let mut xft_color: xft::XftColor = std::mem::zeroed();
+
+			xft::XftColorAllocValue(self.dpy, xlib::XDefaultVisual(self.dpy, self.screen),
+				xlib::XDefaultColormap(self.dpy, self.screen), &render_color, &mut xft_color);
+
+			xft_color
+		}
+	}
+
+	fn free_color(&self, color: &mut xft::XftColor) {
+		unsafe {
+			xft::XftColorFree(self.dpy, xlib::XDefaultVisual(self.dpy, self.screen),
+				xlib::XDefaultColormap(self.dpy, self.screen), color);
+		}
+	}
+
+	fn text_width(&self, font: &DockFont, text: &str) -> i32 {
+		unsafe {
+			match font {
+				DockFont::Xft(font) => {
+					let mut extents: xrender::XGlyphInfo = std::mem::zeroed();
+					xft::XftTextExtentsUtf8(self.dpy, *font, text.as_ptr(), text.len() as i32, &mut extents);
+					extents.xOff as i32
+				},
+				DockFont::Core(font) => {
+					let text = to_latin1(text);
+					xlib::XTextWidth(*font, text.as_ptr() as *const i8, text.len() as i32)
+				}
+			}
+		}
+	}
+
+	fn draw_text(&self, window: &DockWindow, font: &DockFont, x: i32, text: &str, color: &xft::XftColor) {
+		unsafe {
+			let (ascent, descent) = match font {
+				DockFont::Xft(font) => ((**font).ascent, (**font).descent),
+				DockFont::Core(font) => ((**font).ascent, (**font).descent)
+			};
+
+			// Center the line vertically
+			let baseline = (window.height as i32 - (ascent + descent)) / 2 + ascent;
+
+			match font {
+				DockFont::Xft(font) => {
+					xft::XftDrawStringUtf8(window.draw, color, *font, x, baseline, text.as_ptr(), text.len() as i32);
+				},
+				DockFont::Core(font) => {
+					let text = to_latin1(text);
+
+					xlib::XSetFont(self.dpy, self.gc, (**font).fid);
+					xlib::XSetForeground(self.dpy, self.gc, color.pixel);
+					xlib::XDrawString(self.dpy, window.pixmap, self.gc, x, baseline, text.as_ptr() as *const i8, text.len() as i32);
+				}
+			}
+		}
+	}
+
+	fn draw(&self) {
+		let Some(font) = &self.font else { return };
+
+		let mut background = self.alloc_color(self.style.background);
+
+		let widths: Vec<i32> = self.segments.iter().map(|seg| self.text_width(font, &seg.text)).collect();
+		let total: i32 = widths.iter().sum();
+
+		for window in &self.windows {
+			unsafe {
+				xft::XftDrawRect(window.draw, &background, 0, 0, window.width, window.height);
+			}
+
+			// The modules are aligned to the right edge, like in most status bars
+
+			let mut x = (window.width as i32 - total).max(0);
+
+			for (segment, width) in self.segments.iter().zip(&widths) {
+				let (fg, bg) = segment.colors(&self.style);
+
+				if let Some(bg) = bg {
+					let mut bg = self.alloc_color(bg);
+					unsafe { xft::XftDrawRect(window.draw, &bg, x, 0, *width as u32, window.height); }
+					self.free_color(&mut bg);
+				}
+
+				let mut color = self.alloc_color(fg);
+
+				self.draw_text(window, font, x, &segment.text, &color);
+				self.free_color(&mut color);
+
+				x += width;
+			}
+
+			self.present(window);
+		}
+
+		self.free_color(&mut background);
+	}
+
+	fn present(&self, window: &DockWindow) {
+		unsafe {
+			xlib::XCopyArea(self.dpy, window.pixmap, window.win, self.gc, 0, 0, window.width, window.height, 0, 0);
+		}
+	}
+
+	fn update(&mut self, settings: Option<DockSettings>) {
+		let settings = settings.or(self.settings).unwrap_or(DockSettings {
+			position: BarPosition::default(),
+			height: 24
+		});
+
+		if self.windows.is_empty() || self.settings != Some(settings) {
+			self.create_windows(settings);
+		}
+
+		self.draw();
+
+		unsafe {
+			xlib::XFlush(self.dpy);
+		}
+	}
+
+	fn handle_events(&mut self) {
+		let mut monitors_changed = false;
+
+		unsafe {
+			while xlib::XPending(self.dpy) > 0 {
+				let mut event: xlib::XEvent = std::mem::zeroed();
+				xlib::XNextEvent(self.dpy, &mut event);
+
+				match event.get_type() {
+					xlib::Expose => {
+						if let Some(window) = self.windows.iter().find(|w| w.win == event.expose.window) {
+							self.present(window);
+						}
+					},
+					t if Some(t) == self.randr_event_base.map(|base| base + xrandr::RRScreenChangeNotify) => {
+						xrandr::XRRUpdateConfiguration(&mut event);
+						monitors_changed = true;
+					},
+					_ => {}
+				}
+			}
+		}
+
+		if monitors_changed && let Some(settings) = self.settings {
+			self.create_windows(settings);
+			self.update(None);
+		}
+	}
+}
+
+impl Backend for X11DockBackend {
+	fn output(&mut self, config: &Config, blocks: &[Block]) {
+		self.load_font(config.font.as_deref(), config.font_size);
+
+		self.style = Style::from_config(config);
+		self.segments = canvas::layout(config, blocks);
+
+		self.update(Some(DockSettings {
+			position: config.position,
+			height: config.height
+		}));
+	}
+
+	fn output_error(&mut self, err: &str) {
+		if self.font.is_none() {
+			self.load_font(None, self.style.font_size);
+		}
+
+		self.segments = vec![Segment::plain(format!(" {} ", err))];
+
+		self.update(None);
+	}
+
+	fn wait(&mut self, timeout: Duration) -> Option<ClickEvent> {
+		self.handle_events();
+
+		waker::poll(Some(unsafe { xlib::XConnectionNumber(self.dpy) }), timeout);
+
+		self.handle_events();
+
+		None
+	}
+}
+
+impl Drop for X11DockBackend {
+	fn drop(&mut self) {
+		self.destroy_windows();
+		self.free_font();
+
+		unsafe {
+			xlib::XFreeGC(self.dpy, self.gc);
+			xlib::XCloseDisplay(self.dpy);
+		}
+	}
+}
@@ -1,15 +1,20 @@
+use crate::include;
 use crate::utils;
 
-use serde::{Deserialize, Deserializer};
+use itertools::Itertools;
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
 use toml::{Value, Table};
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
 	#[serde(default = "default_spaces::<1>")]
+	#[serde(alias = "leftpad")]
 	pub left_pad: String,
 
 	#[serde(default = "default_spaces::<1>")]
+	#[serde(alias = "rightpad")]
 	pub right_pad: String,
 
 	#[serde(default = "default_spaces::<2>")]
@@ -17,35 +22,169 @@ pub struct Config {
 
 	#[serde(deserialize_with = "deserialize_millis")]
 	#[serde(default = "default_max_interval")]
+	#[serde(alias = "maxinterval")]
 	pub max_interval: Duration,
 
+	// The files (and include patterns) this config has been loaded from, for reload
+	// watching. The main config file always comes first.
 	#[serde(skip)]
-	#[serde(default = "default_mtime")]
-	pub mtime: SystemTime,
+	pub files: Vec<String>,
+
+	#[serde(default)]
+	pub position: BarPosition,
+
+	#[serde(default = "default_height")]
+	pub height: u32,
+
+	pub font: Option<String>,
+
+	#[serde(default = "default_font_size")]
+	pub font_size: f32,
+
+	#[serde(default = "default_foreground")]
+	pub foreground: Color,
+
+	#[serde(default = "default_background")]
+	pub background: Color,
 
 	pub modules: Vec<ModuleConfig>
 }
 
 fn default_spaces<const N: usize>() -> String { " ".repeat(N) }
 fn default_max_interval() -> Duration { Duration::MAX }
-fn default_mtime() -> SystemTime { SystemTime::now() }
+fn default_height() -> u32 { 24 }
+fn default_font_size() -> f32 { 14.0 }
+fn default_foreground() -> Color { Color { r: 0xFF, g: 0xFF, b: 0xFF, a: 0xFF } }
+fn default_background() -> Color { Color { r: 0x00, g: 0x00, b: 0x00, a: 0xFF } }
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BarPosition {
+	#[default]
+	Top,
+	Bottom
+}
 
-fn deserialize_millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
-	let val = Value::deserialize(deserializer)?;
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+	Left,
+	Center,
+	#[default]
+	Right
+}
 
-	if let Value::Integer(dur) = val {
-		return Ok(Duration::from_millis(dur as u64))
+#[derive(Clone, Copy, PartialEq)]
+pub struct Color {
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+	pub a: u8
+}
+
+impl Color {
+	fn parse(val: &str) -> Option<Self> {
+		// Accepts `#rgb`, `#rrggbb` and `#rrggbbaa`
+
+		let hex = val.strip_prefix('#')?;
+
+		if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+			return None
+		}
+
+		let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+		match hex.len() {
+			3 => {
+				let short = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|x| x * 0x11);
+
+				Some(Color { r: short(0)?, g: short(1)?, b: short(2)?, a: 0xFF })
+			},
+			6 => Some(Color { r: component(0)?, g: component(2)?, b: component(4)?, a: 0xFF }),
+			8 => Some(Color { r: component(0)?, g: component(2)?, b: component(4)?, a: component(6)? }),
+			_ => None
+		}
 	}
+}
+
+impl<'de> Deserialize<'de> for Color {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let val = String::deserialize(deserializer)?;
 
-	if let Value::Float(dur) = val {
-		return Ok(Duration::from_secs_f64(dur * 1000.0))
+		Color::parse(&val)
+			.ok_or_else(|| serde::de::Error::custom(format!("Invalid color `{val}`, expected e.g. \"#ff8800\"")))
 	}
+}
 
-	Err(serde::de::Error::custom("Expected max_interval to be an integer or float"))
+// Picks the colors of a module based on its numeric value, e.g. `{ above = 80, color = "#f00" }`
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ColorRule {
+	pub above: Option<f64>,
+	pub below: Option<f64>,
+	pub color: Option<Color>,
+	pub background: Option<Color>
+}
+
+impl ColorRule {
+	pub fn matches(&self, value: f64) -> bool {
+		self.above.is_none_or(|above| value > above) && self.below.is_none_or(|below| value < below)
+	}
+}
+
+// Parses durations like "500ms", "2s" or "1m30s"
+fn parse_duration(text: &str) -> Result<Duration, String> {
+	let invalid = || format!("Invalid duration `{text}`, expected e.g. \"500ms\", \"2s\" or \"1m30s\"");
+
+	let mut rest = text.trim();
+	let mut total = Duration::ZERO;
+
+	if rest.is_empty() {
+		Err(invalid())?
+	}
+
+	while !rest.is_empty() {
+		let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+		let unit_len = rest[number_len..].find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len() - number_len);
+
+		let number: f64 = rest[..number_len].parse().map_err(|_| invalid())?;
+
+		let unit = match &rest[number_len..number_len + unit_len] {
+			"ms" => 0.001,
+			"s" => 1.0,
+			"m" => 60.0,
+			"h" => 3600.0,
+			_ => Err(invalid())?
+		};
+
+		total += Duration::try_from_secs_f64(number * unit).map_err(|_| invalid())?;
+		rest = &rest[number_len + unit_len..];
+	}
+
+	Ok(total)
+}
+
+// Durations are either a number of milliseconds or a string like "1m30s"
+fn deserialize_millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+	let val = Value::deserialize(deserializer)?;
+
+	match val {
+		Value::Integer(dur) => u64::try_from(dur).map(Duration::from_millis)
+			.map_err(|_| serde::de::Error::custom(format!("Invalid duration {dur}, expected a positive number"))),
+		Value::Float(dur) => Duration::try_from_secs_f64(dur / 1000.0)
+			.map_err(|_| serde::de::Error::custom(format!("Invalid duration {dur}, expected a positive number"))),
+		Value::String(dur) => parse_duration(&dur).map_err(serde::de::Error::custom),
+		_ => Err(serde::de::Error::custom("Expected a duration, e.g. 500 (milliseconds) or \"1m30s\""))
+	}
 }
 
 #[derive(serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ModuleConfig {
+	#[serde(alias = "name")]
+	pub id: Option<String>,
+
 	pub icon: Option<String>,
 
 	#[serde(deserialize_with = "deserialize_millis")]
@@ -53,22 +192,52 @@ pub struct ModuleConfig {
 
 	#[serde(deserialize_with = "deserialize_millis")]
 	#[serde(default = "default_start_delay")]
+	#[serde(alias = "startdelay")]
 	pub start_delay: Duration,
 
+	#[serde(deserialize_with = "deserialize_millis")]
+	#[serde(default = "default_timeout")]
+	pub timeout: Duration,
+
 	#[serde(default = "default_false")]
 	pub optional: bool,
 
 	pub unix_signal: Option<u8>,
 
+	pub color: Option<Color>,
+
+	pub background: Option<Color>,
+
+	pub urgent_color: Option<Color>,
+
+	#[serde(default)]
+	pub colors: Vec<ColorRule>,
+
+	#[serde(default)]
+	pub align: Align,
+
+	pub on_click: Option<String>,
+
 	#[serde(deserialize_with = "deserialize_module_impl")]
 	#[serde(rename = "impl")]
 	pub implementation: ModuleImplementationConfig
 }
 
+impl ModuleConfig {
+	// How the module is referred to in logs
+	pub fn label(&self) -> String {
+		match &self.id {
+			Some(id) => format!("{} ({})", self.implementation.name, id),
+			None => self.implementation.name.clone()
+		}
+	}
+}
+
 fn default_false() -> bool { false }
 fn default_start_delay() -> Duration { Duration::ZERO }
+fn default_timeout() -> Duration { Duration::from_secs(2) }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ModuleImplementationConfig {
 	pub name: String,
 	pub config: Table
@@ -95,19 +264,96 @@ fn deserialize_module_impl<'de, D: Deserializer<'de>>(deserializer: D) -> Result
 	})
 }
 
+// Serde only says which fields it expected, so point out the likely typo as well
+fn suggest(message: String) -> String {
+	let Some((_, rest)) = message.split_once("unknown field `") else {
+		return message
+	};
+
+	let Some((field, expected)) = rest.split_once('`') else {
+		return message
+	};
+
+	let normalize = |name: &str| name.to_lowercase().replace(['_', '-'], "");
+
+	let candidates = expected.split('`').skip(1).step_by(2).collect_vec();
+
+	// With just one candidate, serde's message already says it all
+	if candidates.len() < 2 {
+		return message
+	}
+
+	let suggestion = candidates.into_iter()
+		.map(|candidate| (utils::edit_distance(&normalize(field), &normalize(candidate)), candidate))
+		.filter(|(distance, _)| *distance <= field.len().div_ceil(3))
+		.min_by_key(|(distance, _)| *distance);
+
+	match suggestion {
+		Some((_, candidate)) => format!("{}\ndid you mean `{}`?", message.trim_end(), candidate),
+		None => message
+	}
+}
+
+// Used by the modules to read their `impl.{name}` table
+pub fn parse_module_config<T: DeserializeOwned>(name: &str, config: Table) -> Result<T, String> {
+	config.try_into().map_err(|err| suggest(format!("Error reading `{name}` config: {err}")))
+}
+
+pub struct ConfigError {
+	pub message: String,
+	pub line: Option<usize>,
+
+	// Set if the error is in an included file rather than the main one
+	pub file: Option<String>
+}
+
+impl ConfigError {
+	// A short version of the error which fits on the bar
+	pub fn summary(&self) -> String {
+		let file = self.file.as_deref()
+			.and_then(|file| std::path::Path::new(file).file_name())
+			.map(|file| format!(" {}", file.to_string_lossy()))
+			.unwrap_or_default();
+
+		match self.line {
+			Some(line) => format!("config error:{} line {}", file, line),
+			None => format!("config error:{}", file).trim_end_matches(':').to_string()
+		}
+	}
+}
+
+impl From<String> for ConfigError {
+	fn from(message: String) -> Self {
+		ConfigError { message, line: None, file: None }
+	}
+}
+
 impl Config {
-	fn from_toml(config: &str) -> Result<Self, String> {
-		let config = toml::from_str(config).map_err(|err| format!("Error parsing TOML: {err}"))?;
-		Ok(config)
+	fn from_toml(config: &str) -> Result<Self, ConfigError> {
+		toml::from_str(config).map_err(|err| ConfigError {
+			line: utils::line_of(config, err.span()),
+			file: None,
+			message: suggest(format!("Error parsing TOML: {err}"))
+		})
 	}
 }
 
 fn get_xdg_config_path() -> Option<String> {
-	std::env::var_os("XDG_CONFIG_HOME")?.into_string().ok()
+	std::env::var_os("XDG_CONFIG_HOME")?.into_string().ok().filter(|path| !path.is_empty())
 }
 
 fn get_fake_xdg_config_path() -> Option<String> {
-	Some(std::env::var_os("XDG_CONFIG_HOME")?.into_string().ok()? + "/.config")
+	Some(std::env::var_os("HOME")?.into_string().ok()? + "/.config")
+}
+
+fn get_system_config_paths() -> Vec<String> {
+	// A colon-separated list, defaults to /etc/xdg
+
+	let dirs = std::env::var("XDG_CONFIG_DIRS").ok()
+		.filter(|dirs| !dirs.is_empty())
+		.unwrap_or_else(|| "/etc/xdg".to_string());
+
+	dirs.split(':').filter(|dir| !dir.is_empty()).map(str::to_string).collect()
 }
 
 fn get_general_config_path() -> Option<String> {
@@ -120,49 +366,197 @@ fn get_config_path() -> Option<String> {
 	Some(get_general_config_path()? + "/corrodedbar")
 }
 
-pub fn get_config_file_mtime() -> Result<SystemTime, String> {
+fn get_search_paths() -> Vec<String> {
+	get_xdg_config_path().into_iter()
+		.chain(get_fake_xdg_config_path())
+		.chain(get_system_config_paths())
+		.map(|dir| dir + "/corrodedbar/main.toml")
+		.unique()
+		.collect()
+}
+
+fn create_example_config() -> Result<String, String> {
 	let Some(config_dir_path) = get_config_path() else {
 		Err("Could not determine the config directory. Make sure $HOME is set.".to_string())?
 	};
 
-	let config_path = config_dir_path + "/main.toml";
+	let config_path = format!("{}/main.toml", &config_dir_path);
 
-	let metadata = std::fs::metadata(config_path)
-		.map_err(|e| format!("Error fetching config file metadata: {}", e))?;
+	if let Err(e) = std::fs::create_dir_all(&config_dir_path) {
+		Err(format!("Error creating path {}: {}", config_dir_path, e))?
+	}
+
+	if let Err(e) = std::fs::write(&config_path, include_str!("example.toml")) {
+		Err(format!("Error creating config file {}: {}", config_path, e))?
+	}
 
-	let mtime = metadata.modified()
-		.map_err(|e| format!("Error determining config file mtime: {}", e))?;
+	eprintln!("Created an example config file at {}.", config_path);
 
-	Ok(mtime)
+	Ok(config_path)
 }
 
-pub fn load_config() -> Result<Config, String> {
-	let Some(config_dir_path) = get_config_path() else {
-		Err("Could not determine the config directory. Make sure $HOME is set.".to_string())?
-	};
+fn find_config(path: Option<&str>, create: bool) -> Result<String, String> {
+	// A config file given on the command line (or in the environment) has to exist,
+	// otherwise the usual places are searched, and if there is nothing there,
+	// the example config is put into the user's config directory (if `create` is set).
 
-	let config_path = format!("{}/main.toml", &config_dir_path);
+	let explicit = path.map(str::to_string)
+		.or_else(|| std::env::var("CORRODEDBAR_CONFIG").ok().filter(|path| !path.is_empty()));
 
-	let config_contents = utils::read_string(&config_path).or_else(|_| {
-		if let Err(e) = std::fs::create_dir_all(&config_dir_path) {
-			Err(format!("Error creating path {}: {}", config_dir_path, e))?
-		}
+	if let Some(path) = explicit {
+		return Ok(path)
+	}
 
-		let exampleconf = include_str!("example.toml");
+	let search_paths = get_search_paths();
 
-		if let Err(e) = std::fs::write(&config_path, exampleconf) {
-			Err(format!("Error creating config file {}: {}", config_path, e))?
-		}
+	match search_paths.iter().find(|path| std::path::Path::new(path).exists()) {
+		Some(path) => Ok(path.clone()),
+		None if create => create_example_config(),
+		None => Err(format!("No config file found (searched: {})", search_paths.join(", ")))
+	}
+}
 
-		Ok::<String, String>(exampleconf.to_string())
-	})?;
+// Clicks (and the logs) refer to modules by their ids, so they have to be unique.
+// `contents` is the config file, if all modules come straight from it.
+fn check_ids(config: &Config, contents: Option<&str>) -> Result<(), ConfigError> {
+	let duplicates = config.modules.iter()
+		.filter_map(|module| module.id.as_deref())
+		.duplicates()
+		.map(|id| format!("`{}`", id))
+		.join(", ");
+
+	if duplicates.is_empty() {
+		return Ok(())
+	}
 
-	let mut config = Config::from_toml(&config_contents)?;
+	Err(ConfigError {
+		line: contents.and_then(duplicate_id_line),
+		file: None,
+		message: format!("Module ids have to be unique, but these are used more than once: {}", duplicates)
+	})
+}
+
+// Finds the first module which reuses an id
+fn duplicate_id_line(contents: &str) -> Option<usize> {
+	#[derive(serde::Deserialize)]
+	struct Ids {
+		#[serde(default)]
+		modules: Vec<ModuleId>
+	}
 
-	if let Ok(mtime) = get_config_file_mtime() {
-		config.mtime = mtime;
+	#[derive(serde::Deserialize)]
+	struct ModuleId {
+		#[serde(alias = "name")]
+		id: Option<toml::Spanned<String>>
 	}
 
+	let ids: Ids = toml::from_str(contents).ok()?;
+	let mut seen = std::collections::HashSet::new();
+
+	let duplicate = ids.modules.into_iter()
+		.filter_map(|module| module.id)
+		.find(|id| !seen.insert(id.get_ref().clone()))?;
+
+	utils::line_of(contents, Some(duplicate.span()))
+}
+
+fn read_config(path: &str) -> Result<Config, ConfigError> {
+	let contents = std::fs::read_to_string(path)
+		.map_err(|e| format!("Error reading config file {}: {}", path, e))?;
+
+	let mut table: Table = toml::from_str(&contents).map_err(|err| ConfigError {
+		line: utils::line_of(&contents, err.span()),
+		file: None,
+		message: format!("Error parsing TOML: {err}")
+	})?;
+
+	let mut files = vec![path.to_string()];
+
+	// Without includes, parse the file directly, so that errors point to the right line
+
+	let mut config = match table.contains_key("include") {
+		false => {
+			let config = Config::from_toml(&contents)?;
+
+			check_ids(&config, Some(&contents))?;
+
+			config
+		},
+		true => {
+			include::apply(&mut table, path, &mut files, 0)?;
+
+			let config = table.try_into().map_err(|err| suggest(format!("Error in the config (with includes merged): {err}")))?;
+
+			check_ids(&config, None)?;
+
+			config
+		}
+	};
+
+	config.files = files;
+
 	Ok(config)
 }
 
+pub fn load_config(path: Option<&str>, create: bool) -> Result<Config, String> {
+	read_config(&find_config(path, create)?).map_err(|err| err.message)
+}
+
+pub fn reload_config(old: &Config) -> Result<Config, ConfigError> {
+	// Unlike `load_config`, this does not recreate a missing config file
+
+	let config_path = old.files.first()
+		.ok_or_else(|| "The config has not been loaded from a file".to_string())?;
+
+	read_config(config_path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(serde::Deserialize)]
+	struct Interval {
+		#[serde(deserialize_with = "deserialize_millis")]
+		interval: Duration
+	}
+
+	fn interval(toml: &str) -> Result<Duration, toml::de::Error> {
+		toml::from_str::<Interval>(toml).map(|parsed| parsed.interval)
+	}
+
+	#[test]
+	fn integer_millis() {
+		assert_eq!(interval("interval = 0").unwrap(), Duration::ZERO);
+		assert_eq!(interval("interval = 500").unwrap(), Duration::from_millis(500));
+		assert_eq!(interval("interval = 60000").unwrap(), Duration::from_secs(60));
+	}
+
+	#[test]
+	fn float_millis() {
+		assert_eq!(interval("interval = 1.5").unwrap(), Duration::from_micros(1500));
+		assert_eq!(interval("interval = 250.0").unwrap(), Duration::from_millis(250));
+	}
+
+	#[test]
+	fn negative_millis() {
+		assert!(interval("interval = -1").is_err());
+		assert!(interval("interval = -1.0").is_err());
+	}
+
+	#[test]
+	fn duration_strings() {
+		assert_eq!(interval("interval = \"500ms\"").unwrap(), Duration::from_millis(500));
+		assert_eq!(interval("interval = \"2s\"").unwrap(), Duration::from_secs(2));
+		assert_eq!(interval("interval = \"1m30s\"").unwrap(), Duration::from_secs(90));
+		assert_eq!(interval("interval = \"1h\"").unwrap(), Duration::from_secs(3600));
+		assert_eq!(interval("interval = \"1.5s\"").unwrap(), Duration::from_millis(1500));
+	}
+
+	#[test]
+	fn invalid_duration_strings() {
+		for text in ["", "5", "s", "2 s", "2x", "1m30", "-1s"] {
+			assert!(interval(&format!("interval = \"{text}\"")).is_err(), "{text:?} should be rejected");
+		}
+	}
+}
@@ -11,6 +11,12 @@ pub struct FmtGenFloat64 {
 	pub default_fmt: Option<String>
 }
 
+// Each value is formatted with the same options, separated by spaces
+pub struct FmtGenFloat64List {
+	pub vals: Vec<f64>,
+	pub default_fmt: Option<String>
+}
+
 pub struct FmtGenInt64 {
 	pub val: i64,
 	pub default_fmt: Option<String>
@@ -19,6 +25,7 @@ pub struct FmtGenInt64 {
 pub enum FormatGenerator {
 	OutputString(FmtGenString),
 	OutputFloat64(FmtGenFloat64),
+	OutputFloat64List(FmtGenFloat64List),
 	OutputInt64(FmtGenInt64)
 }
 
@@ -57,6 +64,12 @@ macro_rules! fmt_opt {
 			default_fmt: Some($defaultfmt.to_string())
 		})
 	};
+	(f64s raw $val:expr, $defaultfmt:literal) => {
+		formatter::FormatGenerator::OutputFloat64List(formatter::FmtGenFloat64List {
+			vals: $val,
+			default_fmt: Some($defaultfmt.to_string())
+		})
+	};
 	(i64 raw $val:expr) => {
 		formatter::FormatGenerator::OutputInt64(formatter::FmtGenInt64 {
 			val: $val,
@@ -149,6 +162,10 @@ fn parse_fmt_opt_params(opts: &mut [FormatOptionParam], iter: &mut CharIterator)
 }
 
 fn parse_fmt_f64(iter: &mut CharIterator, val: FmtGenFloat64) -> Result<String, String> {
+	parse_fmt_f64_list(iter, FmtGenFloat64List { vals: vec![val.val], default_fmt: val.default_fmt })
+}
+
+fn parse_fmt_f64_list(iter: &mut CharIterator, val: FmtGenFloat64List) -> Result<String, String> {
 	/*
 	 * Float format syntax: `%T[dD pP zZ]`
 	 *   T = token
@@ -162,8 +179,6 @@ fn parse_fmt_f64(iter: &mut CharIterator, val: FmtGenFloat64) -> Result<String,
 	 *   result = fnoutput / D; rounded to P decimal places, zero-padded to Z digits
 	 */
 
-	let mut result = val.val;
-
 	let opts: &mut [FormatOptionParam] = &mut [
 		fmt_opt_param!('d', Float64, 1.0),
 		fmt_opt_param!('p', Usize, 0),
@@ -180,17 +195,19 @@ fn parse_fmt_f64(iter: &mut CharIterator, val: FmtGenFloat64) -> Result<String,
 	let FormatOptionParamVal::Usize(decimals) = opts[1].val else { panic!() };
 	let FormatOptionParamVal::Usize(zeropad) = opts[2].val else { panic!() };
 
-	result /= divisor;
+	let results = val.vals.iter().map(|result| {
+		let mut result_str = format!("{:.decimals$}", (result / divisor).abs(), decimals = decimals);
 
-	let mut result_str = format!("{:.decimals$}", result.abs(), decimals = decimals);
+		let len = result_str.find(|c: char| !c.is_ascii_digit()).unwrap_or(result_str.len());
 
-	let len = result_str.find(|c: char| !c.is_ascii_digit()).unwrap_or(result_str.len());
+		if len < zeropad {
+			result_str = "0".repeat(zeropad - len) + &result_str
+		}
 
-	if len < zeropad {
-		result_str = "0".repeat(zeropad - len) + &result_str
-	}
+		result_str
+	});
 
-	Ok(result_str)
+	Ok(results.collect::<Vec<_>>().join(" "))
 }
 
 fn parse_fmt_i64(iter: &mut CharIterator, val: FmtGenInt64) -> Result<String, String> {
@@ -246,6 +263,7 @@ fn parse_fmt_val(iter: &mut CharIterator, val: FormatGenerator) -> Result<String
 	match val {
 		FormatGenerator::OutputString(val) => Ok(val.val),
 		FormatGenerator::OutputFloat64(val) => parse_fmt_f64(iter, val),
+		FormatGenerator::OutputFloat64List(val) => parse_fmt_f64_list(iter, val),
 		FormatGenerator::OutputInt64(val) => parse_fmt_i64(iter, val)
 	}
 }
@@ -255,11 +273,7 @@ pub fn format<F>(fmt: &str, f: F) -> Result<Option<String>, String> where F: Fn(
 	
 	let mut iter = fmt.chars().peekable();
 
-	loop {
-		let Some(c) = iter.next() else {
-			break
-		};
-
+	while let Some(c) = iter.next() {
 		if c == '%' {
 			let Some(tag) = iter.next() else {
 				break
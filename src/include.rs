@@ -0,0 +1,189 @@
+use crate::config::ConfigError;
+use crate::utils;
+
+use toml::{Table, Value};
+
+use std::path::Path;
+
+// Handles `include = ["modules/*.toml", "host-${HOSTNAME}.toml"]` in config files.
+//
+// The included files are merged over the including file, in the given order:
+// tables are merged key by key, other values are replaced, and `modules` are
+// appended, except for modules with the same `id` (or `name`) as an existing one,
+// which replace it in place.
+
+// Guards against files which (indirectly) include themselves
+const MAX_DEPTH: usize = 8;
+
+fn hostname() -> Option<String> {
+	let mut buf = [0u8; 256];
+
+	if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } < 0 {
+		return None
+	}
+
+	let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+
+	String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+fn expand_vars(pattern: &str) -> Result<String, String> {
+	let mut out = String::new();
+	let mut rest = pattern;
+
+	while let Some(start) = rest.find("${") {
+		let Some(len) = rest[start..].find('}') else { break };
+
+		let name = &rest[start + 2..start + len];
+
+		// $HOSTNAME is usually not exported by the shell, so ask the kernel instead
+
+		let val = std::env::var(name).ok()
+			.or_else(|| (name == "HOSTNAME").then(hostname).flatten())
+			.ok_or_else(|| format!("Unknown variable ${{{}}} in `include`", name))?;
+
+		out += &rest[..start];
+		out += &val;
+		rest = &rest[start + len + 1..];
+	}
+
+	Ok(out + rest)
+}
+
+fn expand_glob(pattern: &str) -> Vec<String> {
+	// Wildcards are only supported in the file name itself. Files which don't
+	// exist are skipped, so that e.g. not every host needs its own overlay.
+
+	let path = Path::new(pattern);
+
+	let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+		return Vec::new()
+	};
+
+	if !name.contains(['*', '?']) {
+		return match path.exists() {
+			true => vec![pattern.to_string()],
+			false => Vec::new()
+		}
+	}
+
+	let Ok(entries) = std::fs::read_dir(dir) else {
+		return Vec::new()
+	};
+
+	let mut files: Vec<String> = entries
+		.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+		.filter(|file| utils::glob_match(name, file))
+		.map(|file| dir.join(file).to_string_lossy().into_owned())
+		.collect();
+
+	files.sort();
+	files
+}
+
+fn parse(path: &str) -> Result<Table, ConfigError> {
+	let contents = std::fs::read_to_string(path)
+		.map_err(|e| format!("Error reading included file {}: {}", path, e))?;
+
+	toml::from_str(&contents).map_err(|err| ConfigError {
+		line: utils::line_of(&contents, err.span()),
+		file: Some(path.to_string()),
+		message: format!("Error parsing {}: {}", path, err)
+	})
+}
+
+// `name` is an alias of `id`, the same as when deserializing the module
+fn module_id(module: &Value) -> Option<&str> {
+	module.get("id").or_else(|| module.get("name"))?.as_str()
+}
+
+fn merge_modules(modules: &mut Vec<Value>, overlay: Vec<Value>) {
+	for module in overlay {
+		let existing = module_id(&module)
+			.and_then(|id| modules.iter().position(|old| module_id(old) == Some(id)));
+
+		match existing {
+			Some(i) => modules[i] = module,
+			None => modules.push(module)
+		}
+	}
+}
+
+fn merge(base: &mut Table, overlay: Table) {
+	for (key, value) in overlay {
+		match (base.get_mut(&key), value) {
+			(Some(Value::Array(modules)), Value::Array(new)) if key == "modules" => merge_modules(modules, new),
+			(Some(Value::Table(table)), Value::Table(new)) => merge(table, new),
+			(_, value) => { base.insert(key, value); }
+		}
+	}
+}
+
+// Resolves the includes of `table` (loaded from `path`), recursively. All files and
+// patterns which should be watched for changes are added to `files`.
+pub fn apply(table: &mut Table, path: &str, files: &mut Vec<String>, depth: usize) -> Result<(), ConfigError> {
+	let Some(include) = table.remove("include") else {
+		return Ok(())
+	};
+
+	if depth >= MAX_DEPTH {
+		Err(format!("Too many nested includes in {} (is a file including itself?)", path))?
+	}
+
+	let patterns: Vec<String> = include.try_into()
+		.map_err(|_| format!("`include` in {} has to be a list of file names", path))?;
+
+	let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+
+	for pattern in patterns {
+		let pattern = dir.join(expand_vars(&pattern)?).to_string_lossy().into_owned();
+
+		// Watch the pattern rather than the matches, so that new files get noticed too
+		files.push(pattern.clone());
+
+		for file in expand_glob(&pattern) {
+			let mut overlay = parse(&file)?;
+
+			apply(&mut overlay, &file, files, depth + 1)?;
+			merge(table, overlay);
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn modules(toml: &str) -> Vec<Value> {
+		toml::from_str::<Table>(toml).unwrap().remove("modules").unwrap().try_into().unwrap()
+	}
+
+	#[test]
+	fn merge_modules_by_id() {
+		let mut base = modules(r#"modules = [{ id = "bat", icon = "B" }, { id = "clock" }]"#);
+
+		merge_modules(&mut base, modules(r#"modules = [{ id = "bat", icon = "b" }, { id = "cpu" }]"#));
+
+		assert_eq!(base, modules(r#"modules = [{ id = "bat", icon = "b" }, { id = "clock" }, { id = "cpu" }]"#));
+	}
+
+	#[test]
+	fn merge_modules_by_name() {
+		let mut base = modules(r#"modules = [{ id = "bat", icon = "B" }, { name = "clock" }]"#);
+
+		merge_modules(&mut base, modules(r#"modules = [{ name = "bat", icon = "b" }, { id = "clock", icon = "C" }]"#));
+
+		assert_eq!(base, modules(r#"modules = [{ name = "bat", icon = "b" }, { id = "clock", icon = "C" }]"#));
+	}
+
+	#[test]
+	fn merge_modules_without_id() {
+		let mut base = modules(r#"modules = [{ icon = "B" }]"#);
+
+		merge_modules(&mut base, modules(r#"modules = [{ icon = "B" }]"#));
+
+		assert_eq!(base.len(), 2);
+	}
+}
@@ -6,30 +6,47 @@ mod backend;
 mod utils;
 mod args;
 mod formatter;
+mod worker;
+mod waker;
+mod reload;
+mod include;
 
 use crate::backend::Backend;
 use crate::backend::x11::X11Backend;
 use crate::backend::stdout::StdoutBackend;
+use crate::backend::i3bar::I3barBackend;
+use crate::backend::lemonbar::LemonbarBackend;
+use crate::backend::wayland::WaylandBackend;
+use crate::backend::x11dock::X11DockBackend;
 
-fn run<B: Backend>(params: &args::AppParams) -> Result<(), String> {
-	let config = config::load_config()?;
+fn run<B: Backend>(backend: &mut B, config: Option<config::Config>, loaded_modules: &mut Vec<modules::ModuleRuntime>, params: &args::AppParams) -> Result<config::Config, String> {
+	let config = match config {
+		Some(config) => config,
+		None => config::load_config(params.config.as_deref(), true)?
+	};
 
-	let mut loaded_modules = modules::init(&config)?;
+	*loaded_modules = modules::init(&config, std::mem::take(loaded_modules));
 	eprintln!("{} module(s) enabled.", loaded_modules.len());
 
-	scheduler::run::<B>(&config, &mut loaded_modules, params);
-
-	Ok(())
+	Ok(scheduler::run(backend, &config, loaded_modules, params))
 }
 
-fn main_with_backend<B: Backend>(params: &args::AppParams) {
+fn main_with_backend<B: Backend>(mut backend: B, params: &args::AppParams) {
+	// The config is validated before the running modules are torn down, so it is
+	// passed straight to the next run.
+	let mut config = None;
+
+	// Modules which did not change are kept running across reloads
+	let mut loaded_modules = Vec::new();
+
 	loop {
-		match run::<B>(params) {
-			Ok(()) => {
+		match run(&mut backend, config.take(), &mut loaded_modules, params) {
+			Ok(new_config) => {
 				eprintln!("Detected config file change, reloading.");
+				config = Some(new_config);
 			},
 			Err(err) => {
-				B::output(&err);
+				backend.output_error(&err);
 				eprintln!("Init failed: {}", err);
 				std::thread::sleep(std::time::Duration::from_millis(1000));
 			}
@@ -37,12 +54,58 @@ fn main_with_backend<B: Backend>(params: &args::AppParams) {
 	}
 }
 
+fn check_config(params: &args::AppParams) -> bool {
+	// Only check what the user wrote, never create the example config
+	let config = match config::load_config(params.config.as_deref(), false) {
+		Ok(config) => config,
+		Err(err) => {
+			println!("{}", err);
+			return false
+		}
+	};
+
+	if let Some(file) = config.files.first() {
+		println!("Checking {}", file);
+	}
+
+	match modules::check(&config) {
+		0 => {
+			println!("No problems found.");
+			true
+		},
+		problems => {
+			println!("{} problem(s) found.", problems);
+			false
+		}
+	}
+}
+
 fn main() {
 	let params = args::init();
 
+	if params.check_config {
+		std::process::exit(if check_config(&params) { 0 } else { 1 })
+	}
+
 	match params.backend.as_str() {
-		"x11" => main_with_backend::<X11Backend>(&params),
-		"stdout" => main_with_backend::<StdoutBackend>(&params),
+		"x11" => main_with_backend(X11Backend::default(), &params),
+		"stdout" => main_with_backend(StdoutBackend { waybar: params.waybar }, &params),
+		"i3bar" => main_with_backend(I3barBackend::default(), &params),
+		"lemonbar" => main_with_backend(LemonbarBackend {}, &params),
+		"x11-dock" => match X11DockBackend::new() {
+			Ok(backend) => main_with_backend(backend, &params),
+			Err(err) => {
+				eprintln!("{}", err);
+				std::process::exit(1)
+			}
+		},
+		"wayland" => match WaylandBackend::new() {
+			Ok(backend) => main_with_backend(backend, &params),
+			Err(err) => {
+				eprintln!("{}", err);
+				std::process::exit(1)
+			}
+		},
 		x => {
 			eprintln!("Invalid backend: {}", x);
 			std::process::exit(1)
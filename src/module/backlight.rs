@@ -1,3 +1,4 @@
+use crate::config;
 use crate::modules;
 use crate::utils;
 use crate::formatter;
@@ -6,15 +7,20 @@ use crate::fmt_opt;
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Backlight {
 	device_curr: String,
 	device_max: String,
 	
 	#[serde(default = "default_format")]
-	format: String
+	format: String,
+
+	#[serde(default = "default_scroll_step")]
+	scroll_step: f64
 }
 
 fn default_format() -> String { "%u%%".to_string() }
+fn default_scroll_step() -> f64 { 5.0 }
 
 impl Backlight {
 	fn get_value(&self) -> Result<Option<i64>, String> {
@@ -35,23 +41,51 @@ impl Backlight {
 
 		Ok(Some(curr / max))
 	}
+
+	fn set_value(&self, val: i64) -> Result<(), String> {
+		std::fs::write(&self.device_curr, val.to_string())
+			.map_err(|e| format!("Error setting brightness: {}", e))
+	}
 }
 
 impl modules::ModuleImplementation for Backlight {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
-		formatter::format(&self.format, |tag| {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		let percentage = self.get_value_perc()?.map(|x| x * 100.0);
+
+		let text = formatter::format(&self.format, |tag| {
 			match tag {
 				'c' => fmt_opt!(i64 self.get_value()),
 				'u' => fmt_opt!(f64 self.get_value_perc(), "[d.01]"),
 				'm' => fmt_opt!(i64 self.get_max_value()),
 				_ => Ok(None)
 			}
-		})
+		})?;
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			value: percentage,
+			is_percentage: true,
+			..text.into()
+		}))
+	}
+
+	fn click(&mut self, event: &modules::ClickEvent) -> Result<(), String> {
+		let direction = match event.button {
+			modules::BUTTON_SCROLL_UP => 1,
+			modules::BUTTON_SCROLL_DOWN => -1,
+			_ => return Ok(())
+		};
+
+		let curr = self.get_value()?.unwrap();
+		let max = self.get_max_value()?.unwrap();
+
+		let step = ((max as f64) * self.scroll_step / 100.0).round().max(1.0) as i64;
+
+		self.set_value((curr + direction * step).clamp(0, max))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Backlight = Table::try_into(config).map_err(|err| format!("Error reading `time` config: {err}"))?;
+	let new: Backlight = config::parse_module_config("backlight", config)?;
 
 	Ok(Box::new(new))
 }
@@ -1,3 +1,4 @@
+use crate::config;
 use crate::modules;
 use crate::utils;
 use crate::formatter;
@@ -6,6 +7,7 @@ use crate::fmt_opt;
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Battery {
 	device: String,
 	
@@ -13,11 +15,16 @@ struct Battery {
 	format: String,
 
 	#[serde(default = "default_est_time_format")]
-	est_time_format: String
+	#[serde(alias = "esttimeformat")]
+	est_time_format: String,
+
+	#[serde(default = "default_critical")]
+	critical: f64
 }
 
 fn default_format() -> String { "%i %p%% (%w W %e)".to_string() }
 fn default_est_time_format() -> String { "%h:%M".to_string() }
+fn default_critical() -> f64 { 15.0 }
 
 impl Battery {
 	fn get_icon(&self) -> Result<Option<String>, String> {
@@ -118,8 +125,11 @@ impl Battery {
 }
 
 impl modules::ModuleImplementation for Battery {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
-		formatter::format(&self.format, |tag| {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		let status = utils::read_line(&format!("/sys/class/power_supply/{}/status", self.device)).unwrap_or_default();
+		let capacity = self.get_capacity().ok().map(|x| x * 100.0);
+
+		let text = formatter::format(&self.format, |tag| {
 			match tag {
 				'i' => fmt_opt!(String self.get_icon()),
 				'p' => fmt_opt!(f64 self.get_capacity().map(Some), "[d.01]"),
@@ -127,12 +137,29 @@ impl modules::ModuleImplementation for Battery {
 				'e' => fmt_opt!(String self.get_estimate()),
 				_ => Ok(None)
 			}
-		})
+		})?;
+
+		let mut classes = Vec::new();
+
+		if !status.is_empty() {
+			classes.push(status.to_lowercase().replace(' ', "-"));
+		}
+
+		if status == "Discharging" && capacity.is_some_and(|x| x <= self.critical) {
+			classes.push("critical".to_string());
+		}
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			value: capacity,
+			is_percentage: true,
+			classes,
+			..text.into()
+		}))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Battery = Table::try_into(config).map_err(|err| format!("Error reading `time` config: {err}"))?;
+	let new: Battery = config::parse_module_config("battery", config)?;
 
 	Ok(Box::new(new))
 }
@@ -1,8 +1,10 @@
+use crate::config;
 use crate::modules;
 
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Bluetooth {
 	#[serde(default = "default_enabled")]
 	enabled: String
@@ -11,7 +13,7 @@ struct Bluetooth {
 fn default_enabled() -> String { "enabled".to_string() }
 
 impl modules::ModuleImplementation for Bluetooth {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let mut is_enabled = false;
 
 		unsafe {
@@ -45,12 +47,20 @@ impl modules::ModuleImplementation for Bluetooth {
 			libc::close(file);
 		}
 
-		Ok(is_enabled.then(|| self.enabled.to_string()))
+		Ok(is_enabled.then(|| self.enabled.to_string().into()))
+	}
+
+	fn watch(&mut self) -> Option<modules::WakeSource> {
+		// rfkill sends an event whenever a radio gets (un)blocked
+
+		let file = std::fs::File::open("/dev/rfkill").ok()?;
+
+		Some(modules::WakeSource::Fd(file.into()))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Bluetooth = Table::try_into(config).map_err(|err| format!("Error reading `bluetooth` config: {err}"))?;
+	let new: Bluetooth = config::parse_module_config("bluetooth", config)?;
 
 	Ok(Box::new(new))
 }
@@ -1,3 +1,4 @@
+use crate::config;
 use crate::modules;
 use crate::utils;
 use crate::formatter;
@@ -5,16 +6,94 @@ use crate::fmt_opt;
 
 use toml::Table;
 
+use std::time::Duration;
+
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Cpu {
 	temp_device: String,
 
 	#[serde(default = "default_format")]
-	format: String
+	format: String,
+
+	// The utilization is computed from the difference between two runs of the module
+
+	#[serde(skip)]
+	last_stat: Option<(Duration, Vec<CpuTimes>)>,
+
+	#[serde(skip)]
+	last_usage: Option<Vec<CpuUsage>>,
+
+	// Only shown if the format actually uses the utilization
+	#[serde(skip)]
+	usage_error: Option<String>
 }
 
 fn default_format() -> String { "%t°C %F MHz".to_string() }
 
+// Runs closer together than this (e.g. after a click) reuse the last utilization,
+// as the counters (in 10 ms ticks) would hardly have moved
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+// One `cpu*` line of /proc/stat, see proc(5)
+#[derive(Clone, Copy, Default)]
+struct CpuTimes {
+	busy: u64,
+	idle: u64,
+	iowait: u64,
+	steal: u64
+}
+
+// Everything as a fraction of the time between the two samples
+#[derive(Clone, Copy)]
+struct CpuUsage {
+	busy: f64,
+	iowait: f64,
+	steal: f64
+}
+
+// The first entry is the total, then one for each core
+fn read_stat() -> Result<Vec<CpuTimes>, String> {
+	let stat = utils::read_string("/proc/stat")?;
+
+	stat.lines()
+		.take_while(|line| line.starts_with("cpu"))
+		.map(|line| {
+			let fields: Vec<u64> = line.split_whitespace().skip(1)
+				.map(|field| field.parse::<u64>().map_err(|e| format!("Format error in /proc/stat: {}", e)))
+				.collect::<Result<_, _>>()?;
+
+			// user nice system idle iowait irq softirq steal (guest time is included in user)
+
+			let [user, nice, system, idle, iowait, irq, softirq, steal, ..] = fields[..] else {
+				Err("Format error in /proc/stat: too few fields".to_string())?
+			};
+
+			Ok(CpuTimes {
+				busy: user + nice + system + irq + softirq,
+				idle,
+				iowait,
+				steal
+			})
+		})
+		.collect()
+}
+
+fn usage_between(old: &CpuTimes, new: &CpuTimes) -> CpuUsage {
+	let busy = new.busy.saturating_sub(old.busy) as f64;
+	let idle = new.idle.saturating_sub(old.idle) as f64;
+	let iowait = new.iowait.saturating_sub(old.iowait) as f64;
+	let steal = new.steal.saturating_sub(old.steal) as f64;
+
+	let total = (busy + idle + iowait + steal).max(1.0);
+
+	CpuUsage {
+		busy: busy / total,
+		iowait: iowait / total,
+		steal: steal / total
+	}
+}
+
 impl Cpu {
 	fn try_get_temp(&self) -> Result<Option<f64>, String> {
 		let curr_temp: f64 = utils::read_line_as(&self.temp_device)?;
@@ -97,25 +176,73 @@ impl Cpu {
 	fn get_lowest_freq(&self, proc_cpu_info: &str) -> Result<Option<f64>, String> {
 		self.get_freq(proc_cpu_info, false)
 	}
+
+	fn update_usage(&mut self, ts: Duration) -> Result<(), String> {
+		if let Some((last_ts, _)) = &self.last_stat
+			&& self.last_usage.is_some()
+			&& ts.saturating_sub(*last_ts) < MIN_SAMPLE_INTERVAL
+		{
+			return Ok(())
+		}
+
+		let stat = read_stat()?;
+
+		// On the first run, this is the average since boot
+
+		let usage = match &self.last_stat {
+			Some((_, last)) if last.len() == stat.len() => stat.iter().zip(last).map(|(new, old)| usage_between(old, new)).collect(),
+			_ => stat.iter().map(|new| usage_between(&CpuTimes::default(), new)).collect()
+		};
+
+		self.last_stat = Some((ts, stat));
+		self.last_usage = Some(usage);
+
+		Ok(())
+	}
+
+	fn get_usage(&self, usage: impl Fn(&CpuUsage) -> f64) -> Result<Option<f64>, String> {
+		if let Some(err) = &self.usage_error { Err(err.clone())? }
+
+		Ok(self.last_usage.as_ref().and_then(|cpus| cpus.first()).map(usage))
+	}
+
+	fn get_core_usage(&self) -> Result<Option<Vec<f64>>, String> {
+		if let Some(err) = &self.usage_error { Err(err.clone())? }
+
+		Ok(self.last_usage.as_ref().map(|cpus| cpus.iter().skip(1).map(|cpu| cpu.busy).collect()))
+	}
 }
 
 impl modules::ModuleImplementation for Cpu {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, ts: Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let proc_cpu_info = utils::read_string("/proc/cpuinfo")?;
 
-		formatter::format(&self.format, |tag| {
+		self.usage_error = self.update_usage(ts).err();
+
+		let temp = self.get_temp().ok().flatten().map(|x| x / 1000.0);
+
+		let text = formatter::format(&self.format, |tag| {
 			match tag {
 				't' => fmt_opt!(f64 self.get_temp(), "[d1000 p1]"),
 				'F' => fmt_opt!(f64 self.get_highest_freq(&proc_cpu_info)),
 				'f' => fmt_opt!(f64 self.get_lowest_freq(&proc_cpu_info)),
+				'u' => fmt_opt!(f64 self.get_usage(|cpu| cpu.busy), "[d.01]"),
+				'U' => fmt_opt!(f64s self.get_core_usage(), "[d.01]"),
+				'w' => fmt_opt!(f64 self.get_usage(|cpu| cpu.iowait), "[d.01]"),
+				's' => fmt_opt!(f64 self.get_usage(|cpu| cpu.steal), "[d.01]"),
 				_ => Ok(None)
 			}
-		})
+		})?;
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			value: temp,
+			..text.into()
+		}))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Cpu = Table::try_into(config).map_err(|err| format!("Error reading `cpu` config: {err}"))?;
+	let new: Cpu = config::parse_module_config("cpu", config)?;
 
 	Ok(Box::new(new))
 }
@@ -0,0 +1,95 @@
+use crate::config;
+use crate::modules;
+use crate::utils;
+use crate::formatter;
+use crate::fmt_opt;
+
+use toml::Table;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Load {
+	#[serde(default = "default_format")]
+	format: String,
+
+	#[serde(default = "default_false")]
+	normalize: bool
+}
+
+fn default_format() -> String { "%a %b %c".to_string() }
+fn default_false() -> bool { false }
+
+// The contents of /proc/loadavg, e.g. "0.42 0.32 0.28 2/73 27124", see proc(5)
+struct LoadAvg {
+	loads: [f64; 3],
+	running: i64,
+	total: i64,
+	last_pid: i64
+}
+
+fn read_loadavg() -> Result<LoadAvg, String> {
+	let line = utils::read_line("/proc/loadavg")?;
+
+	let fields: Vec<&str> = line.split_whitespace().collect();
+
+	let [one, five, fifteen, tasks, last_pid] = fields[..] else {
+		Err(format!("Format error in /proc/loadavg: {}", line))?
+	};
+
+	let Some((running, total)) = tasks.split_once('/') else {
+		Err(format!("Format error in /proc/loadavg: {}", line))?
+	};
+
+	let float = |val: &str| val.parse::<f64>().map_err(|e| format!("Format error: {:?}", e));
+	let int = |val: &str| val.parse::<i64>().map_err(|e| format!("Format error: {:?}", e));
+
+	Ok(LoadAvg {
+		loads: [float(one)?, float(five)?, float(fifteen)?],
+		running: int(running)?,
+		total: int(total)?,
+		last_pid: int(last_pid)?
+	})
+}
+
+fn online_cpus() -> Result<usize, String> {
+	match unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } {
+		n if n > 0 => Ok(n as usize),
+		_ => Err(format!("Error getting the number of CPUs: {}", std::io::Error::last_os_error()))
+	}
+}
+
+impl modules::ModuleImplementation for Load {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		let loadavg = read_loadavg()?;
+		let cpus = online_cpus()?;
+
+		// With `normalize`, 1.0 means that all CPUs are busy
+		let divisor = if self.normalize { cpus as f64 } else { 1.0 };
+		let [one, five, fifteen] = loadavg.loads.map(|load| load / divisor);
+
+		let text = formatter::format(&self.format, |tag| {
+			Ok(Some(match tag {
+				'a' => fmt_opt!(f64 raw one, "[p2]"),
+				'b' => fmt_opt!(f64 raw five, "[p2]"),
+				'c' => fmt_opt!(f64 raw fifteen, "[p2]"),
+				'r' => fmt_opt!(i64 raw loadavg.running),
+				't' => fmt_opt!(i64 raw loadavg.total),
+				'p' => fmt_opt!(i64 raw loadavg.last_pid),
+				'n' => fmt_opt!(i64 raw cpus as i64),
+				_ => return Ok(None)
+			}))
+		})?;
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			// For the color rules only, it is not a percentage
+			value: Some(one),
+			..text.into()
+		}))
+	}
+}
+
+pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
+	let new: Load = config::parse_module_config("load", config)?;
+
+	Ok(Box::new(new))
+}
@@ -1,3 +1,4 @@
+use crate::config;
 use crate::modules;
 use crate::formatter;
 use crate::fmt_opt;
@@ -6,6 +7,7 @@ use crate::utils;
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Memory {
 	#[serde(default = "default_format")]
 	format: String
@@ -30,7 +32,7 @@ fn calculate_value(total: f64, free: f64, percentage: bool, used: bool) -> Resul
 }
 
 impl modules::ModuleImplementation for Memory {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let file = utils::read_string("/proc/meminfo")?;
 
 		let lines = file.lines();
@@ -67,7 +69,9 @@ impl modules::ModuleImplementation for Memory {
 			}
 		}
 
-		formatter::format(&self.format, |tag| {
+		let percentage = calculate_value(total, free, true, true)?.map(|x| x * 100.0);
+
+		let text = formatter::format(&self.format, |tag| {
 			match tag {
 				'p' => fmt_opt!(f64 calculate_value(total, free, true, true), "[d.01]"),
 				'P' => fmt_opt!(f64 calculate_value(total, free, true, false), "[d.01]"),
@@ -79,12 +83,18 @@ impl modules::ModuleImplementation for Memory {
 				'W' => fmt_opt!(f64 calculate_value(swap_total, swap_free, false, false)),
 				_ => Ok(None)
 			}
-		})
+		})?;
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			value: percentage,
+			is_percentage: true,
+			..text.into()
+		}))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Memory = Table::try_into(config).map_err(|err| format!("Error reading `memory` config: {err}"))?;
+	let new: Memory = config::parse_module_config("memory", config)?;
 
 	Ok(Box::new(new))
 }
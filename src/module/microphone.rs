@@ -1,3 +1,4 @@
+use crate::config;
 use crate::modules;
 
 use pulsectl::controllers::SourceController;
@@ -5,6 +6,7 @@ use pulsectl::controllers::AppControl;
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct MicrophoneConfig {
 	#[serde(default = "default_active")]
 	active: String
@@ -18,16 +20,16 @@ struct Microphone {
 }
 
 impl modules::ModuleImplementation for Microphone {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let apps = self.handler.list_applications()
 			.map_err(|e| format!("PulseAudio error: {}", e))?;
 
-		Ok((!apps.is_empty()).then(|| self.config.active.to_string()))
+		Ok((!apps.is_empty()).then(|| self.config.active.to_string().into()))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let config: MicrophoneConfig = Table::try_into(config).map_err(|err| format!("Error reading `microphone` config: {err}"))?;
+	let config: MicrophoneConfig = config::parse_module_config("microphone", config)?;
 
 	Ok(Box::new(Microphone {
 		config,
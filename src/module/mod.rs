@@ -2,10 +2,12 @@ pub mod backlight;
 pub mod battery;
 pub mod bluetooth;
 pub mod cpu;
+pub mod load;
 pub mod memory;
 pub mod microphone;
 pub mod network;
 pub mod time;
+pub mod traffic;
 pub mod uptime;
 pub mod volume;
 
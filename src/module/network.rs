@@ -1,69 +1,434 @@
+use crate::config;
 use crate::modules;
+use crate::utils;
+use crate::formatter;
+use crate::fmt_opt;
 
 use dbus::blocking::Connection;
-use networkmanager::devices::{Any, Device};
+use dbus::message::MatchRule;
+use networkmanager::devices::{Any, Device, Wired, Wireless};
 use networkmanager::NetworkManager;
 use itertools::Itertools;
 use toml::Table;
 
+use std::ffi::CStr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkConfig {
+	#[serde(default = "default_format")]
+	format: String,
+
+	// Accepts `*` and `?` wildcards, e.g. "wlp*"
+	#[serde(default)]
+	interfaces: Vec<String>,
+
+	#[serde(default = "default_separator")]
+	separator: String,
+
+	#[serde(default)]
+	source: SourceKind
+}
+
+fn default_format() -> String { "%a".to_string() }
+fn default_separator() -> String { " ".to_string() }
+
+#[derive(serde::Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum SourceKind {
+	// NetworkManager if it is running, the kernel otherwise
+	#[default]
+	Auto,
+	NetworkManager,
+	Kernel
+}
+
+enum Source {
+	NetworkManager(Connection),
+
+	// getifaddrs(3), /proc/net and /sys/class/net, for systems without NetworkManager
+	Kernel
+}
+
 struct Network {
-	dbus: Connection,
+	config: NetworkConfig,
+	source: Source,
+	// The NetworkManager watcher thread exits once this is dropped
+	alive: Arc<()>
 }
 
+// Everything the format can show about a single interface
+#[derive(Default)]
+struct Interface {
+	name: String,
+	ipv4: Vec<String>,
+	ipv6: Vec<String>,
+	connection: Option<String>,
+	ssid: Option<String>,
+	strength: Option<u8>,
+	speed: Option<u32>,
+	gateway: Option<String>
+}
+
+// `ip` is in network byte order, like `sin_addr.s_addr`
 fn ipv4_format(ip: u32, mask: u32) -> String {
-	format!("{}.{}.{}.{}/{}",
-		ip & 0xFF,
-		(ip >> 8) & 0xFF,
-		(ip >> 16) & 0xFF,
-		ip >> 24,
-		mask
-	)
+	format!("{}/{}", Ipv4Addr::from(u32::from_be(ip)), mask)
 }
 
-fn get_addrlist<T: Any>(dev: T) -> Option<Vec<Vec<u32>>> {
-	let Ok(cfg) = dev.ip4_config() else { None? };
-	
-	let Ok(addr_list) = cfg.addresses() else { None? };
+fn ipv6_format(ip: &[u8], prefix: u32) -> Option<String> {
+	let ip = Ipv6Addr::from(<[u8; 16]>::try_from(ip).ok()?);
+
+	// Link-local addresses are always there and not very interesting
+	if ip.is_unicast_link_local() { None? }
 
-	Some(addr_list)
+	Some(format!("{}/{}", ip, prefix))
 }
 
-impl modules::ModuleImplementation for Network {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
-		let mut ips: Vec<String> = Vec::new();
+fn get_interface<T: Any>(dev: &T) -> Interface {
+	let ipv4 = dev.ip4_config().and_then(|cfg| cfg.addresses())
+		.map(|addrs| addrs.iter().filter(|ip| ip.len() >= 2).map(|ip| ipv4_format(ip[0], ip[1])).collect())
+		.unwrap_or_default();
+
+	let ipv6 = dev.ip6_config().and_then(|cfg| cfg.addresses())
+		.map(|addrs| addrs.iter().filter_map(|(ip, prefix, _)| ipv6_format(ip, *prefix)).collect())
+		.unwrap_or_default();
+
+	Interface {
+		name: dev.interface().unwrap_or_default(),
+		ipv4,
+		ipv6,
+		connection: dev.active_connection().and_then(|conn| conn.id()).ok(),
+		gateway: dev.ip4_config().and_then(|cfg| cfg.gateway()).ok().filter(|gateway| !gateway.is_empty()),
+		..Default::default()
+	}
+}
+
+fn nm_interfaces(dbus: &Connection, explicit: bool) -> Option<Vec<Interface>> {
+	let nm = NetworkManager::new(dbus);
+
+	let devices = nm.get_devices().ok()?;
+
+	let interfaces = devices.into_iter().filter_map(|device| {
+		Some(match device {
+			Device::WiFi(wifi) => {
+				let ap = wifi.active_access_point().ok();
+
+				Interface {
+					ssid: ap.as_ref().and_then(|ap| ap.ssid().ok()),
+					strength: ap.as_ref().and_then(|ap| ap.strength().ok()),
+					// Reported in kb/s
+					speed: wifi.bitrate().ok().map(|rate| rate / 1000),
+					..get_interface(&wifi)
+				}
+			},
+			Device::Ethernet(eth) => Interface {
+				speed: eth.speed().ok(),
+				..get_interface(&eth)
+			},
+			Device::Generic(dev) if explicit => get_interface(&dev),
+			Device::Bridge(dev) if explicit => get_interface(&dev),
+			Device::Veth(dev) if explicit => get_interface(&dev),
+			_ => None?
+		})
+	});
+
+	Some(interfaces.collect())
+}
+
+fn netmask_len(mask: &[u8]) -> u32 {
+	mask.iter().map(|byte| byte.count_ones()).sum()
+}
+
+// All interfaces with their addresses, in the order the kernel lists them
+fn kernel_addresses() -> Result<Vec<Interface>, String> {
+	let mut interfaces: Vec<Interface> = Vec::new();
+
+	let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+
+	if unsafe { libc::getifaddrs(&mut addrs) } < 0 {
+		Err(format!("Error listing network interfaces: {}", std::io::Error::last_os_error()))?
+	}
+
+	let mut next = addrs;
+
+	while !next.is_null() {
+		let ifa = unsafe { &*next };
+		next = ifa.ifa_next;
+
+		let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().into_owned();
+
+		let index = match interfaces.iter().position(|interface| interface.name == name) {
+			Some(index) => index,
+			None => {
+				interfaces.push(Interface { name, ..Default::default() });
+				interfaces.len() - 1
+			}
+		};
+
+		if ifa.ifa_addr.is_null() { continue }
+
+		match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+			libc::AF_INET => {
+				let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+
+				let mask = match ifa.ifa_netmask.is_null() {
+					true => 32,
+					false => unsafe { &*(ifa.ifa_netmask as *const libc::sockaddr_in) }.sin_addr.s_addr.count_ones()
+				};
+
+				interfaces[index].ipv4.push(ipv4_format(addr.sin_addr.s_addr, mask));
+			},
+			libc::AF_INET6 => {
+				let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+
+				let prefix = match ifa.ifa_netmask.is_null() {
+					true => 128,
+					false => netmask_len(&unsafe { &*(ifa.ifa_netmask as *const libc::sockaddr_in6) }.sin6_addr.s6_addr)
+				};
+
+				interfaces[index].ipv6.extend(ipv6_format(&addr.sin6_addr.s6_addr, prefix));
+			},
+			_ => {}
+		}
+	}
+
+	unsafe { libc::freeifaddrs(addrs) };
+
+	Ok(interfaces)
+}
+
+// The IPv4 default gateway of the given interface, from /proc/net/route
+fn kernel_gateway(routes: &str, name: &str) -> Option<String> {
+	routes.lines().skip(1).find_map(|line| {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+
+		// Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+		let [iface, destination, gateway, _, _, _, _, mask, ..] = fields[..] else { None? };
+
+		if iface != name || destination != "00000000" || mask != "00000000" { None? }
+
+		// Printed as hex in the same byte order as `sin_addr.s_addr`
+		let gateway = u32::from_str_radix(gateway, 16).ok()?;
+
+		Some(ipv4_format(gateway, 32).trim_end_matches("/32").to_string())
+	})
+}
+
+// The Wi-Fi link quality in percent, from /proc/net/wireless
+fn kernel_strength(wireless: &str, name: &str) -> Option<u8> {
+	wireless.lines().skip(2).find_map(|line| {
+		let (iface, stats) = line.split_once(':')?;
+
+		if iface.trim() != name { None? }
+
+		// Status, then the link quality (out of 70 for most drivers)
+		let quality: f64 = stats.split_whitespace().nth(1)?.trim_end_matches('.').parse().ok()?;
 
-		let nm = NetworkManager::new(&self.dbus);
+		Some((quality / 70.0 * 100.0).clamp(0.0, 100.0) as u8)
+	})
+}
+
+fn kernel_interfaces(explicit: bool) -> Result<Vec<Interface>, String> {
+	let routes = utils::read_string("/proc/net/route").unwrap_or_default();
+	let wireless = utils::read_string("/proc/net/wireless").unwrap_or_default();
+
+	let interfaces = kernel_addresses()?.into_iter().filter_map(|interface| {
+		let sysfs = format!("/sys/class/net/{}", interface.name);
+
+		// "unknown" is what e.g. loopback and most tunnels report
+		let state = utils::read_line(&format!("{}/operstate", sysfs)).ok()?;
+
+		if state != "up" && state != "unknown" { None? }
+
+		// Like with NetworkManager, only show the physical interfaces by default
+		let physical = Path::new(&format!("{}/device", sysfs)).exists();
+		let wifi = Path::new(&format!("{}/wireless", sysfs)).exists();
+
+		if !explicit && !physical { None? }
+
+		Some(Interface {
+			// Not available for Wi-Fi (and -1 while the link is down)
+			speed: (!wifi).then(|| utils::read_line_as::<i64>(&format!("{}/speed", sysfs)).ok())
+				.flatten()
+				.and_then(|speed| u32::try_from(speed).ok()),
+			strength: kernel_strength(&wireless, &interface.name),
+			gateway: kernel_gateway(&routes, &interface.name),
+			..interface
+		})
+	});
+
+	Ok(interfaces.collect())
+}
+
+// Wakes the module up whenever a link, address or route changes
+fn netlink_socket() -> Option<OwnedFd> {
+	let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+
+	if fd < 0 { None? }
+
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+	let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+	addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+	addr.nl_groups = (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR | libc::RTMGRP_IPV4_ROUTE) as u32;
+
+	let bound = unsafe {
+		libc::bind(fd.as_raw_fd(), &addr as *const libc::sockaddr_nl as *const libc::sockaddr, size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+	};
+
+	(bound == 0).then_some(fd)
+}
+
+fn connect_nm() -> Result<Connection, String> {
+	let dbus = Connection::new_system()
+		.map_err(|e| format!("D-Bus conn error: {}", e))?;
+
+	let proxy = dbus.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", Duration::from_secs(1));
+
+	let (running,): (bool,) = proxy.method_call("org.freedesktop.DBus", "NameHasOwner", ("org.freedesktop.NetworkManager",))
+		.map_err(|e| format!("D-Bus error: {}", e))?;
+
+	if !running {
+		Err("NetworkManager is not running".to_string())?
+	}
+
+	Ok(dbus)
+}
+
+fn watch_properties(tx: Sender<()>, alive: Weak<()>) -> Result<(), dbus::Error> {
+	// The module's own connection lives on another thread, so open a new one
+
+	let dbus = Connection::new_system()?;
+
+	let closed = Arc::new(AtomicBool::new(false));
+	let closed_cb = closed.clone();
+
+	let rule = MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+		.with_sender("org.freedesktop.NetworkManager");
 
-		let Ok(devices) = nm.get_devices() else {
+	dbus.add_match(rule, move |_: (), _, _| {
+		if tx.send(()).is_err() {
+			closed_cb.store(true, Ordering::Relaxed);
+		}
+
+		true
+	})?;
+
+	// Check after every timeout as well, otherwise the thread would outlive the
+	// module until NetworkManager says something
+
+	while !closed.load(Ordering::Relaxed) && alive.strong_count() > 0 {
+		dbus.process(Duration::from_secs(1))?;
+	}
+
+	Ok(())
+}
+
+impl Network {
+	fn get_interfaces(&self) -> Result<Option<Vec<Interface>>, String> {
+		// Without an explicit list of interfaces, only Wi-Fi and Ethernet are shown
+
+		let explicit = !self.config.interfaces.is_empty();
+
+		let interfaces = match &self.source {
+			Source::NetworkManager(dbus) => nm_interfaces(dbus, explicit),
+			Source::Kernel => Some(kernel_interfaces(explicit)?)
+		};
+
+		Ok(interfaces.map(|interfaces| interfaces.into_iter()
+			.filter(|interface| !explicit || self.config.interfaces.iter().any(|pattern| utils::glob_match(pattern, &interface.name)))
+			// Not connected
+			.filter(|interface| !interface.ipv4.is_empty() || !interface.ipv6.is_empty())
+			.collect()))
+	}
+
+	fn format(&self, interface: &Interface) -> Result<Option<String>, String> {
+		let text = |val: Option<&String>| fmt_opt!(String raw val.cloned().unwrap_or_default());
+
+		formatter::format(&self.config.format, |tag| {
+			Ok(Some(match tag {
+				'i' => fmt_opt!(String raw interface.name.clone()),
+				'a' => fmt_opt!(String raw interface.ipv4.iter().join(" ")),
+				'A' => fmt_opt!(String raw interface.ipv6.iter().join(" ")),
+				'c' => text(interface.connection.as_ref()),
+				'g' => text(interface.gateway.as_ref()),
+				's' => text(interface.ssid.as_ref()),
+				'q' => match interface.strength {
+					Some(strength) => fmt_opt!(i64 raw strength as i64),
+					None => fmt_opt!(String raw String::new())
+				},
+				'l' => match interface.speed {
+					Some(speed) => fmt_opt!(i64 raw speed as i64),
+					None => fmt_opt!(String raw String::new())
+				},
+				_ => return Ok(None)
+			}))
+		})
+	}
+}
+
+impl modules::ModuleImplementation for Network {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		let Some(interfaces) = self.get_interfaces()? else {
 			return Ok(None)
 		};
 
-		for device in devices {
-			let ip_list = match device {
-				Device::WiFi(wifi) => get_addrlist(wifi),
-				Device::Ethernet(eth) => get_addrlist(eth),
-				_ => None // TODO - other interfaces, IPv6
-			};
+		let texts = interfaces.iter()
+			.map(|interface| self.format(interface))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let texts = texts.into_iter().flatten().filter(|text| !text.trim().is_empty()).collect_vec();
+
+		if texts.is_empty() {
+			return Ok(None)
+		}
 
-			let Some(ip_list) = ip_list else {
-				continue
-			};
+		Ok(Some(modules::ModuleOutput {
+			// The Wi-Fi signal strength, for the color rules
+			value: interfaces.iter().find_map(|interface| interface.strength).map(f64::from),
+			is_percentage: true,
+			..texts.join(&self.config.separator).into()
+		}))
+	}
 
-			ips.extend(ip_list.iter().map(|ip| ipv4_format(ip[0], ip[1])));
+	fn watch(&mut self) -> Option<modules::WakeSource> {
+		if let Source::Kernel = self.source {
+			return netlink_socket().map(modules::WakeSource::Fd)
 		}
 
-		Ok((!ips.is_empty()).then(|| ips.iter().join(" ")))
+		let (tx, rx) = mpsc::channel();
+		let alive = Arc::downgrade(&self.alive);
+
+		std::thread::spawn(move || {
+			if let Err(err) = watch_properties(tx, alive) {
+				eprintln!("Error watching NetworkManager: {}", err);
+			}
+		});
+
+		Some(modules::WakeSource::Channel(rx))
 	}
 }
 
-pub fn init(_config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	// TODO - specific connection
-	
-	let dbus = dbus::blocking::Connection::new_system()
-		.map_err(|e| format!("D-Bus conn error: {}", e))?;
+pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
+	let config: NetworkConfig = config::parse_module_config("network", config)?;
+
+	let source = match config.source {
+		SourceKind::NetworkManager => Source::NetworkManager(connect_nm()?),
+		SourceKind::Kernel => Source::Kernel,
+		SourceKind::Auto => connect_nm().map(Source::NetworkManager).unwrap_or(Source::Kernel)
+	};
 
 	Ok(Box::new(Network {
-		dbus
+		config,
+		source,
+		alive: Arc::new(())
 	}))
 }
 
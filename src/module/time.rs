@@ -1,8 +1,10 @@
+use crate::config;
 use crate::modules;
 
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Time {
 	#[serde(default = "default_format")]
 	format: String
@@ -11,13 +13,13 @@ struct Time {
 fn default_format() -> String { "%H:%M".to_string() }
 
 impl modules::ModuleImplementation for Time {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
-		Ok(Some(chrono::Local::now().format(&self.format).to_string()))
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		Ok(Some(chrono::Local::now().format(&self.format).to_string().into()))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Time = Table::try_into(config).map_err(|err| format!("Error reading `time` config: {err}"))?;
+	let new: Time = config::parse_module_config("time", config)?;
 
 	Ok(Box::new(new))
 }
@@ -0,0 +1,180 @@
+use crate::config;
+use crate::modules;
+use crate::utils;
+use crate::formatter;
+use crate::fmt_opt;
+
+use toml::Table;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Traffic {
+	#[serde(default = "default_format")]
+	format: String,
+
+	// Both accept `*` and `?` wildcards, e.g. "wlp*"
+	#[serde(default)]
+	interfaces: Vec<String>,
+
+	#[serde(default = "default_exclude")]
+	exclude: Vec<String>,
+
+	#[serde(default = "default_false")]
+	per_interface: bool,
+
+	#[serde(default = "default_separator")]
+	separator: String,
+
+	// Rates are measured against the real time, as runs triggered by signals or
+	// clicks don't move the module's timestamp
+	#[serde(skip)]
+	last: Option<(Instant, HashMap<String, Counters>)>
+}
+
+fn default_format() -> String { "↓%d ↑%u".to_string() }
+fn default_exclude() -> Vec<String> { vec!["lo".to_string()] }
+fn default_false() -> bool { false }
+fn default_separator() -> String { " ".to_string() }
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+	rx: u64,
+	tx: u64
+}
+
+#[derive(Clone, Copy, Default)]
+struct Rates {
+	rx: f64,
+	tx: f64
+}
+
+// Returns the counters of every interface, in the order of /proc/net/dev
+fn read_counters() -> Result<Vec<(String, Counters)>, String> {
+	let dev = utils::read_string("/proc/net/dev")?;
+
+	// The first two lines are headers
+	dev.lines().skip(2)
+		.map(|line| {
+			let Some((name, stats)) = line.split_once(':') else {
+				Err(format!("Format error in /proc/net/dev: {}", line))?
+			};
+
+			let fields: Vec<&str> = stats.split_whitespace().collect();
+
+			let (Some(rx), Some(tx)) = (fields.first(), fields.get(8)) else {
+				Err(format!("Format error in /proc/net/dev: {}", line))?
+			};
+
+			let parse = |val: &str| val.parse::<u64>().map_err(|e| format!("Format error: {:?}", e));
+
+			Ok((name.trim().to_string(), Counters { rx: parse(rx)?, tx: parse(tx)? }))
+		})
+		.collect()
+}
+
+// Auto-scales to B/s, KiB/s, MiB/s or GiB/s
+fn format_rate(rate: f64) -> String {
+	const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+
+	let mut rate = rate;
+	let mut unit = 0;
+
+	while rate >= 1024.0 && unit < UNITS.len() - 1 {
+		rate /= 1024.0;
+		unit += 1;
+	}
+
+	match unit {
+		0 => format!("{:.0} {}", rate, UNITS[unit]),
+		_ => format!("{:.1} {}", rate, UNITS[unit])
+	}
+}
+
+impl Traffic {
+	fn is_selected(&self, name: &str) -> bool {
+		(self.interfaces.is_empty() || self.interfaces.iter().any(|pattern| utils::glob_match(pattern, name)))
+			&& !self.exclude.iter().any(|pattern| utils::glob_match(pattern, name))
+	}
+
+	fn format(&self, name: &str, rates: Rates) -> Result<Option<String>, String> {
+		formatter::format(&self.format, |tag| {
+			Ok(Some(match tag {
+				'i' => fmt_opt!(String raw name.to_string()),
+				'd' => fmt_opt!(String raw format_rate(rates.rx)),
+				'u' => fmt_opt!(String raw format_rate(rates.tx)),
+				'D' => fmt_opt!(f64 raw rates.rx),
+				'U' => fmt_opt!(f64 raw rates.tx),
+				_ => return Ok(None)
+			}))
+		})
+	}
+}
+
+impl modules::ModuleImplementation for Traffic {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
+		let now = Instant::now();
+
+		let counters: Vec<(String, Counters)> = read_counters()?.into_iter()
+			.filter(|(name, _)| self.is_selected(name))
+			.collect();
+
+		// On the first run (and for new interfaces) there is nothing to compare to yet,
+		// so the rates start out at zero
+
+		let rates: Vec<(&str, Rates)> = counters.iter()
+			.map(|(name, new)| {
+				let rates = match &self.last {
+					Some((since, last)) => last.get(name).map(|old| {
+						let elapsed = now.duration_since(*since).as_secs_f64().max(0.001);
+
+						// Counters get reset when e.g. a USB adapter is reconnected
+						Rates {
+							rx: new.rx.saturating_sub(old.rx) as f64 / elapsed,
+							tx: new.tx.saturating_sub(old.tx) as f64 / elapsed
+						}
+					}),
+					None => None
+				};
+
+				(name.as_str(), rates.unwrap_or_default())
+			})
+			.collect();
+
+		let total = rates.iter().fold(Rates::default(), |total, (_, rates)| Rates {
+			rx: total.rx + rates.rx,
+			tx: total.tx + rates.tx
+		});
+
+		let text = match self.per_interface {
+			true => {
+				let parts = rates.iter()
+					.map(|(name, rates)| self.format(name, *rates))
+					.collect::<Result<Vec<_>, _>>()?;
+
+				Some(parts.into_iter().flatten().collect::<Vec<_>>().join(&self.separator))
+			},
+			false => {
+				let names = rates.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(",");
+
+				self.format(&names, total)?
+			}
+		};
+
+		self.last = Some((now, counters.into_iter().collect()));
+
+		Ok(text.map(|text| modules::ModuleOutput {
+			// For the color rules only, it is not a percentage
+			value: Some(total.rx + total.tx),
+			..text.into()
+		}))
+	}
+}
+
+pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
+	let new: Traffic = config::parse_module_config("traffic", config)?;
+
+	Ok(Box::new(new))
+}
@@ -1,9 +1,11 @@
+use crate::config;
 use crate::modules;
 use crate::utils;
 
 use toml::Table;
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 struct Uptime {
 	#[serde(default = "default_format")]
 	format: String
@@ -12,15 +14,15 @@ struct Uptime {
 fn default_format() -> String { "%dd %Hh %Mm".to_string() }
 
 impl modules::ModuleImplementation for Uptime {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let uptime: f64 = utils::read_line_as("/proc/uptime")?;
 
-		utils::format_duration(&self.format, uptime)
+		Ok(utils::format_duration(&self.format, uptime)?.map(Into::into))
 	}
 }
 
 pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
-	let new: Uptime = Table::try_into(config).map_err(|err| format!("Error reading `uptime` config: {err}"))?;
+	let new: Uptime = config::parse_module_config("uptime", config)?;
 
 	Ok(Box::new(new))
 }
@@ -1,15 +1,73 @@
+use crate::config;
 use crate::modules;
 
 use pulsectl::controllers::SinkController;
 use pulsectl::controllers::DeviceControl;
+use pulsectl::Handler;
+use libpulse_binding::context::subscribe::InterestMaskSet;
+use libpulse_binding::time::MicroSeconds;
 use toml::Table;
 
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VolumeConfig {
+	#[serde(default = "default_scroll_step")]
+	scroll_step: f64
+}
+
+fn default_scroll_step() -> f64 { 5.0 }
+
 struct Volume {
-	handler: SinkController
+	config: VolumeConfig,
+	handler: SinkController,
+	// The watcher thread exits once this is dropped
+	alive: Arc<()>
+}
+
+const PA_VOLUME_NORM: f64 = 65536.0;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn watch_sinks(tx: Sender<()>, alive: Weak<()>) -> Result<(), String> {
+	// The module's own connection lives on another thread, so open a new one
+
+	let handler = Handler::connect("corrodedbar")
+		.map_err(|e| format!("PulseAudio conn error: {}", e))?;
+
+	let closed = Rc::new(Cell::new(false));
+	let closed_cb = closed.clone();
+
+	handler.context.borrow_mut().set_subscribe_callback(Some(Box::new(move |_, _, _| {
+		if tx.send(()).is_err() {
+			closed_cb.set(true);
+		}
+	})));
+
+	handler.context.borrow_mut().subscribe(InterestMaskSet::SINK | InterestMaskSet::SERVER, |_| {});
+
+	// Wake up regularly, otherwise the thread would outlive the module
+	// until the next PulseAudio event
+
+	while !closed.get() && alive.strong_count() > 0 {
+		let mut mainloop = handler.mainloop.borrow_mut();
+
+		mainloop.prepare(Some(MicroSeconds(WATCH_INTERVAL.as_micros() as u64)))
+			.and_then(|_| mainloop.poll())
+			.and_then(|_| mainloop.dispatch())
+			.map_err(|e| format!("PulseAudio error: {}", e))?;
+	}
+
+	Ok(())
 }
 
 impl modules::ModuleImplementation for Volume {
-	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<String>, String> {
+	fn run(&mut self, _ts: std::time::Duration) -> Result<Option<modules::ModuleOutput>, String> {
 		let dev = self.handler.get_default_device()
 			.map_err(|e| format!("Error getting default device: {}", e))?;
 
@@ -18,16 +76,53 @@ impl modules::ModuleImplementation for Volume {
 			false => dev.volume.get()[0].to_string().trim().to_string()
 		};
 
-		Ok(Some(val))
+		Ok(Some(modules::ModuleOutput {
+			value: Some(dev.volume.get()[0].0 as f64 * 100.0 / PA_VOLUME_NORM),
+			is_percentage: true,
+			classes: dev.mute.then(|| "muted".to_string()).into_iter().collect(),
+			..val.into()
+		}))
+	}
+
+	fn click(&mut self, event: &modules::ClickEvent) -> Result<(), String> {
+		let dev = self.handler.get_default_device()
+			.map_err(|e| format!("Error getting default device: {}", e))?;
+
+		let step = self.config.scroll_step / 100.0;
+
+		match event.button {
+			modules::BUTTON_LEFT => self.handler.set_device_mute_by_index(dev.index, !dev.mute),
+			modules::BUTTON_SCROLL_UP => self.handler.increase_device_volume_by_percent(dev.index, step),
+			modules::BUTTON_SCROLL_DOWN => self.handler.decrease_device_volume_by_percent(dev.index, step),
+			_ => {}
+		}
+
+		Ok(())
+	}
+
+	fn watch(&mut self) -> Option<modules::WakeSource> {
+		let (tx, rx) = mpsc::channel();
+		let alive = Arc::downgrade(&self.alive);
+
+		std::thread::spawn(move || {
+			if let Err(err) = watch_sinks(tx, alive) {
+				eprintln!("{}", err);
+			}
+		});
+
+		Some(modules::WakeSource::Channel(rx))
 	}
 }
 
-pub fn init(_config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
+pub fn init(config: Table) -> Result<Box<dyn modules::ModuleImplementation>, String> {
+	let config: VolumeConfig = config::parse_module_config("volume", config)?;
+
 	let handler = SinkController::create()
 		.map_err(|e| format!("PulseAudio conn error: {}", e))?;
 
 	Ok(Box::new(Volume {
-		handler
+		config,
+		handler,
+		alive: Arc::new(())
 	}))
 }
-
@@ -1,18 +1,106 @@
-use crate::config::{Config, ModuleConfig};
-use crate::module::{backlight, battery, bluetooth, cpu, memory, microphone, network, time, uptime, volume};
+use crate::config::{Color, Config, ModuleConfig};
+use crate::utils;
+use crate::worker::Worker;
+use crate::module::{backlight, battery, bluetooth, cpu, load, memory, microphone, network, time, traffic, uptime, volume};
 
 use itertools::Itertools;
 use toml::Table;
 
-use std::time::Duration;
+use std::os::fd::OwnedFd;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+#[derive(serde::Deserialize)]
+pub struct ClickEvent {
+	#[serde(default)]
+	pub instance: String,
+
+	pub button: u32,
+
+	#[serde(default)]
+	pub modifiers: Vec<String>,
+
+	#[serde(default)]
+	pub x: i32,
+
+	#[serde(default)]
+	pub y: i32
+}
+
+pub const BUTTON_LEFT: u32 = 1;
+pub const BUTTON_SCROLL_UP: u32 = 4;
+pub const BUTTON_SCROLL_DOWN: u32 = 5;
+
+// Everything a module can tell the backends about its current state. Backends
+// which only display plain text (x11, stdout) just use `full_text`.
+#[derive(Clone, Default, PartialEq)]
+pub struct ModuleOutput {
+	pub full_text: String,
+
+	// Used by backends which shorten the blocks when running out of space
+	pub short_text: Option<String>,
+
+	pub urgent: bool,
+	pub color: Option<Color>,
+	pub background: Option<Color>,
+
+	// A numeric value for the color rules, e.g. the battery capacity in percent
+	pub value: Option<f64>,
+
+	// Set if `value` is a percentage, which backends can show as a gauge
+	pub is_percentage: bool,
+
+	pub tooltip: Option<String>,
+
+	// Describes the state after the last run (e.g. `discharging`), so that
+	// backends can style the module accordingly.
+	pub classes: Vec<String>
+}
+
+impl From<String> for ModuleOutput {
+	fn from(full_text: String) -> Self {
+		ModuleOutput { full_text, ..Default::default() }
+	}
+}
+
+// Lets a module tell the scheduler that its state has changed, so that it does not
+// have to be polled with a tiny `interval`.
+pub enum WakeSource {
+	// Becomes readable on every change (whatever is read gets discarded)
+	Fd(OwnedFd),
+
+	// Receives a message on every change
+	Channel(Receiver<()>)
+}
 
 pub trait ModuleImplementation {
-	fn run(&mut self, ts: Duration) -> Result<Option<String>, String>;
+	fn run(&mut self, ts: Duration) -> Result<Option<ModuleOutput>, String>;
+
+	fn click(&mut self, _event: &ClickEvent) -> Result<(), String> {
+		Ok(())
+	}
+
+	// Called once after the module has been initialized
+	fn watch(&mut self) -> Option<WakeSource> {
+		None
+	}
 }
 
 pub struct ModuleRuntime {
-	pub module: Box<dyn ModuleImplementation>,
-	pub config: ModuleConfig
+	pub worker: Worker,
+	pub config: ModuleConfig,
+
+	// The scheduler's bookkeeping, kept across config reloads
+	pub started: Instant,
+	pub counter: Duration,
+	pub output: Option<ModuleOutput>
+}
+
+impl ModuleRuntime {
+	// Whether the running module can be kept when switching to the given config
+	fn can_reuse(&self, config: &ModuleConfig) -> bool {
+		self.config.implementation == config.implementation && self.config.timeout == config.timeout
+	}
 }
 
 macro_rules! register_module {
@@ -21,44 +109,124 @@ macro_rules! register_module {
 	};
 }
 
-type ModuleInitFun = fn(Table) -> Result<Box<dyn ModuleImplementation>, String>;
-
-pub fn init(config: &Config) -> Result<Vec<ModuleRuntime>, String> {
-	let available_modules: &[(&str, ModuleInitFun)] = &[
-		register_module!(battery),
-		register_module!(backlight),
-		register_module!(bluetooth),
-		register_module!(cpu),
-		register_module!(memory),
-		register_module!(microphone),
-		register_module!(network),
-		register_module!(time),
-		register_module!(uptime),
-		register_module!(volume)
-	];
-
-	let loaded_modules = config.modules.iter().enumerate()
+pub type ModuleInitFun = fn(Table) -> Result<Box<dyn ModuleImplementation>, String>;
+
+const AVAILABLE_MODULES: &[(&str, ModuleInitFun)] = &[
+	register_module!(battery),
+	register_module!(backlight),
+	register_module!(bluetooth),
+	register_module!(cpu),
+	register_module!(load),
+	register_module!(memory),
+	register_module!(microphone),
+	register_module!(network),
+	register_module!(time),
+	register_module!(traffic),
+	register_module!(uptime),
+	register_module!(volume)
+];
+
+fn find_module(name: &str) -> Result<ModuleInitFun, String> {
+	if let Some(module) = AVAILABLE_MODULES.iter().find(|module| module.0 == name) {
+		return Ok(module.1)
+	}
+
+	let suggestion = AVAILABLE_MODULES.iter()
+		.map(|module| (utils::edit_distance(name, module.0), module.0))
+		.filter(|(distance, _)| *distance <= name.len().div_ceil(3))
+		.min_by_key(|(distance, _)| *distance)
+		.map(|(_, module)| format!(", did you mean `{}`?", module))
+		.unwrap_or_default();
+
+	Err(format!("Unknown module implementation `{}`{}", name, suggestion))
+}
+
+// Modules from `old` (i.e. the previous config) whose implementation config has not
+// changed are kept running, everything else gets initialized from scratch.
+pub fn init(config: &Config, old: Vec<ModuleRuntime>) -> Vec<ModuleRuntime> {
+	let mut old = old.into_iter().map(Some).collect_vec();
+
+	config.modules.iter().enumerate()
 		.filter_map(|(i, module_config)| {
-			eprintln!("[{}/{}] Initializing module {}", i + 1, config.modules.len(), module_config.implementation.name);
-			
-			let module_init = available_modules.iter()
-				.filter(|module| module.0 == module_config.implementation.name)
-				.map(|module| module.1).next();
+			let reusable = old.iter_mut()
+				.find(|runtime| runtime.as_ref().is_some_and(|runtime| runtime.can_reuse(module_config)))
+				.and_then(Option::take);
+
+			if let Some(runtime) = reusable {
+				eprintln!("[{}/{}] Keeping module {}", i + 1, config.modules.len(), module_config.label());
 
-			let Some(module_init) = module_init else {
-				eprintln!(" -> does not exist, skipping");
-				None?
+				// A new interval takes effect right away
+				let counter = match runtime.config.interval == module_config.interval {
+					true => runtime.counter,
+					false => runtime.started.elapsed()
+				};
+
+				return Some(ModuleRuntime {
+					config: module_config.clone(),
+					counter,
+					..runtime
+				})
+			}
+
+			eprintln!("[{}/{}] Initializing module {}", i + 1, config.modules.len(), module_config.label());
+			
+			let module_init = match find_module(&module_config.implementation.name) {
+				Ok(module_init) => module_init,
+				Err(err) => {
+					eprintln!(" -> {}, skipping", err);
+					None?
+				}
 			};
 
-			module_init(module_config.implementation.config.clone())
+			Worker::spawn(&module_config.label(), module_init, module_config.implementation.config.clone(), module_config.timeout)
 				.map_err(|err| eprintln!(" -> {}", err)).ok()
-				.map(|module| ModuleRuntime {
-					module,
-					config: module_config.clone()
+				.map(|worker| ModuleRuntime {
+					worker,
+					config: module_config.clone(),
+					started: Instant::now(),
+					counter: module_config.start_delay,
+					output: None
 				})
 		})
-		.collect_vec();
+		.collect_vec()
+}
+
+// Initializes every module and runs it once, the same way the bar would, but reports
+// all problems instead of skipping the broken modules. Returns the number of problems.
+pub fn check(config: &Config) -> usize {
+	let mut problems = 0;
+
+	for (i, module_config) in config.modules.iter().enumerate() {
+		let name = &module_config.implementation.name;
+		let label = module_config.label();
+
+		let result = find_module(name)
+			.and_then(|init| Worker::spawn(&label, init, module_config.implementation.config.clone(), module_config.timeout))
+			.and_then(|mut worker| {
+				worker.run(Duration::ZERO);
+
+				loop {
+					if let Some(result) = worker.poll() {
+						break result.map(|_| ())
+					}
+
+					crate::waker::poll(None, Duration::from_millis(100));
+				}
+			});
+
+		match result {
+			Ok(()) => println!("[{}/{}] {}: OK", i + 1, config.modules.len(), label),
+			// Optional modules are allowed to fail, e.g. a battery module on a desktop
+			Err(err) if module_config.optional && find_module(name).is_ok() => {
+				println!("[{}/{}] {}: {} (optional, ignored)", i + 1, config.modules.len(), label, err);
+			},
+			Err(err) => {
+				println!("[{}/{}] {}: {}", i + 1, config.modules.len(), label, err);
+				problems += 1;
+			}
+		}
+	}
 
-	Ok(loaded_modules)
+	problems
 }
 
@@ -0,0 +1,165 @@
+use crate::utils;
+use crate::waker;
+
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+// Watches the config file(s) for changes. Many editors save files by writing a new
+// file and renaming it over the old one, so the directories are watched instead of
+// the files themselves.
+
+// Editors tend to touch the file several times when saving, wait for things to settle down
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// How often the watcher thread checks whether it is still needed
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_CREATE | libc::IN_DELETE;
+
+pub enum Status {
+	Unchanged,
+	Settling(Duration),
+	Changed
+}
+
+pub struct ConfigWatcher {
+	last_change: Arc<Mutex<Option<Instant>>>
+}
+
+struct Watch {
+	wd: i32,
+
+	// May contain wildcards, for included files
+	name: String
+}
+
+fn watch(fd: OwnedFd, watches: Vec<Watch>, last_change: Weak<Mutex<Option<Instant>>>) {
+	let mut buf = [0u8; 4096];
+
+	loop {
+		let mut pollfd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+
+		if unsafe { libc::poll(&mut pollfd, 1, WATCH_INTERVAL.as_millis() as i32) } < 0 {
+			let err = std::io::Error::last_os_error();
+
+			// Signals (e.g. for `unix_signal`) may land on this thread as well
+			if err.kind() == std::io::ErrorKind::Interrupted {
+				continue
+			}
+
+			eprintln!("Error watching the config: {}, autoreload is off", err);
+			break
+		}
+
+		let Some(last_change) = last_change.upgrade() else { break };
+
+		if pollfd.revents & libc::POLLIN == 0 { continue }
+
+		let len = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+		if len <= 0 { break }
+
+		// Each event is a `struct inotify_event`, followed by the NUL-padded file name
+
+		let mut relevant = false;
+		let mut offset = 0;
+
+		while offset + size_of::<libc::inotify_event>() <= len as usize {
+			let event = unsafe { std::ptr::read_unaligned(buf.as_ptr().add(offset) as *const libc::inotify_event) };
+
+			let name_start = offset + size_of::<libc::inotify_event>();
+			let name = &buf[name_start..(name_start + event.len as usize).min(len as usize)];
+			let name = &name[..name.iter().position(|c| *c == 0).unwrap_or(name.len())];
+
+			relevant |= watches.iter().any(|watch| watch.wd == event.wd && utils::glob_match(&watch.name, &String::from_utf8_lossy(name)));
+
+			offset = name_start + event.len as usize;
+		}
+
+		if relevant {
+			*last_change.lock().unwrap() = Some(Instant::now());
+			waker::wake();
+		}
+	}
+}
+
+impl ConfigWatcher {
+	pub fn new(files: &[String]) -> Result<Self, String> {
+		let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+
+		if fd < 0 {
+			Err(format!("Error initializing inotify: {}", std::io::Error::last_os_error()))?
+		}
+
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+		let mut watches = Vec::new();
+
+		for file in files {
+			// If the config is a symlink (e.g. into a dotfiles repo), watch the real file as well
+
+			let path = PathBuf::from(file);
+			let mut candidates = vec![path.clone()];
+
+			if let Ok(real) = std::fs::canonicalize(&path) && real != path {
+				candidates.push(real);
+			}
+
+			for candidate in candidates {
+				let (Some(mut dir), Some(mut name)) = (candidate.parent(), candidate.file_name()) else { continue };
+
+				// A directory which does not exist yet (e.g. for includes) can't be watched,
+				// so watch for it being created in the nearest existing ancestor instead
+
+				while !dir.as_os_str().is_empty() && !dir.is_dir() {
+					let (Some(parent), Some(dir_name)) = (dir.parent(), dir.file_name()) else { break };
+
+					(dir, name) = (parent, dir_name);
+				}
+
+				let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+				let c_dir = CString::new(dir.as_os_str().as_bytes())
+					.map_err(|e| format!("Invalid path {}: {}", dir.display(), e))?;
+
+				let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), WATCH_MASK) };
+
+				// Better to miss changes in one place than to not watch anything at all
+				if wd < 0 {
+					eprintln!("Error watching {}: {}, skipping", dir.display(), std::io::Error::last_os_error());
+					continue
+				}
+
+				watches.push(Watch { wd, name: name.to_string_lossy().into_owned() });
+			}
+		}
+
+		let last_change = Arc::new(Mutex::new(None));
+		let weak = Arc::downgrade(&last_change);
+
+		std::thread::spawn(move || watch(fd, watches, weak));
+
+		Ok(ConfigWatcher { last_change })
+	}
+
+	pub fn status(&self) -> Status {
+		let mut last_change = self.last_change.lock().unwrap();
+
+		let Some(since) = *last_change else {
+			return Status::Unchanged
+		};
+
+		let elapsed = since.elapsed();
+
+		if elapsed < DEBOUNCE {
+			return Status::Settling(DEBOUNCE - elapsed)
+		}
+
+		*last_change = None;
+		Status::Changed
+	}
+}
@@ -1,22 +1,18 @@
-use crate::backend::Backend;
-use crate::config::{self, Config};
+use crate::backend::{Backend, Block};
+use crate::config::{self, Align, Color, Config, ModuleConfig};
 use crate::modules;
 use crate::args;
+use crate::waker;
+use crate::reload::{ConfigWatcher, Status};
 use std::time::{Duration, Instant};
 use signal_hook::iterator::Signals;
 
-pub fn run<B: Backend>(config: &Config, modules: &mut Vec<modules::ModuleRuntime>, params: &args::AppParams) {
-	let mut counters: Vec<Duration> = Vec::new();
+// Runs until the config file changes, then returns the new config
+pub fn run<B: Backend>(backend: &mut B, config: &Config, modules: &mut [modules::ModuleRuntime], params: &args::AppParams) -> Config {
 	let mut interrupts: Vec<bool> = vec![false; modules.len()];
-	let mut strings: Vec<Option<String>> = vec![None; modules.len()];
-	
-	for module in &mut *modules {
-		counters.push(module.config.start_delay);
-	}
-
-	let start = Instant::now();
 
-	let mut last_output = "".to_string();
+	let mut changed = true;
+	let mut shown = false;
 
 	let mut signal_ids: Vec<i32> = Vec::new();
 
@@ -26,19 +22,47 @@ pub fn run<B: Backend>(config: &Config, modules: &mut Vec<modules::ModuleRuntime
 		}
 	}
 
+	for signal in &signal_ids {
+		if let Err(err) = waker::watch_signal(*signal) {
+			eprintln!("{}", err);
+		}
+	}
+
 	let mut signals = Signals::new(signal_ids).unwrap();
 
-	let old_config_mtime = config::get_config_file_mtime();
+	let watcher = match params.noautoreload {
+		true => None,
+		false => ConfigWatcher::new(&config.files)
+			.map_err(|err| eprintln!("Config auto-reload disabled: {}", err)).ok()
+	};
+
+	let mut click = None;
+
+	// Shown on the bar when the config file has been broken by an edit
+	let mut config_error: Option<String> = None;
 
 	loop {
-		// Check if the config file has been modified
-		
-		if !params.noautoreload
-			&& let Ok(old) = old_config_mtime
-			&& let Ok(val) = config::get_config_file_mtime()
-			&& val != old
-		{
-			return
+		// Anything which happens from now on will wake us up again
+
+		waker::drain();
+
+		// Check if the config file has been modified. The new config is only
+		// used if it is valid, otherwise the bar keeps running as it is.
+
+		let mut reload_in = None;
+
+		match watcher.as_ref().map(ConfigWatcher::status) {
+			Some(Status::Changed) => match config::reload_config(config) {
+				Ok(new_config) => return new_config,
+				Err(err) => {
+					eprintln!("Not reloading the config, keeping the old one.\n{}", err.message);
+
+					config_error = Some(err.summary());
+					changed = true;
+				}
+			},
+			Some(Status::Settling(left)) => reload_in = Some(left),
+			Some(Status::Unchanged) | None => {}
 		}
 
 		// Run each scheduled module
@@ -55,84 +79,220 @@ pub fn run<B: Backend>(config: &Config, modules: &mut Vec<modules::ModuleRuntime
 			}
 		}
 
-		let mut elapsed = start.elapsed();
-
 		for i in 0..modules.len() {
-			if elapsed < counters[i] && !interrupts[i] { continue }
+			if modules[i].worker.take_changed() {
+				interrupts[i] = true;
 
-			if params.verbose {
-				eprintln!("Running module {}.", &modules[i].config.implementation.name);
+				if params.verbose {
+					eprintln!("Module {} reported a change.", modules[i].config.label());
+				}
 			}
+		}
+
+		if let Some(event) = click.take() {
+			handle_click(modules, &mut interrupts, event, params);
+		}
+
+		// Collect the results of the modules which have finished (or timed out)
+
+		for module in modules.iter_mut() {
+			let Some(result) = module.worker.poll() else { continue };
 
-			strings[i] = match modules[i].module.run(counters[i]) {
+			let output = match result {
 				Ok(val) => val,
 				Err(err) => {
 					if params.verbose {
-						eprintln!(" -> {}", err);
+						eprintln!("Module {} failed: {}", module.config.label(), err);
 					}
 
-					(!modules[i].config.optional).then_some(err)
+					(!module.config.optional).then(|| modules::ModuleOutput {
+						urgent: true,
+						..err.into()
+					})
 				}
 			};
 
-			if interrupts[i] {
-				interrupts[i] = false;
-			} else {
-				counters[i] += modules[i].config.interval;
+			if output != module.output {
+				module.output = output;
+				changed = true;
 			}
 		}
 
-		// Generate the output string
-		
-		let mut output = config.left_pad.clone();
+		for (module, interrupt) in modules.iter_mut().zip(interrupts.iter_mut()) {
+			if module.started.elapsed() < module.counter && !*interrupt { continue }
 
-		for i in 0..strings.len() {
-			if let Some(val) = &strings[i] {
-				if let Some(val) = &modules[i].config.icon {
-					output += val;
-					output += " ";
+			if module.worker.is_running() {
+				if params.verbose {
+					eprintln!("Module {} is still running, skipping.", module.config.label());
 				}
 
-				output += val;
-
-				if i < strings.len() - 1 {
-					output += &config.delim;
+				// Run it again as soon as it finishes
+				if *interrupt { continue }
+			} else {
+				if params.verbose {
+					eprintln!("Running module {}.", module.config.label());
 				}
+
+				module.worker.run(module.counter);
+			}
+
+			if *interrupt {
+				*interrupt = false;
+			} else {
+				module.counter += module.config.interval;
 			}
 		}
 
-		output += &config.right_pad;
+		// Pass the individual module outputs to the backend. The very first update
+		// waits for the modules to finish, so that the bar does not start out empty.
+
+		let pending = modules.iter().any(|module| module.worker.is_pending());
+
+		if changed && (shown || !pending) {
+			let mut blocks: Vec<Block> = Vec::new();
+
+			if let Some(text) = &config_error {
+				blocks.push(Block {
+					name: "corrodedbar",
+					instance: "config-error".to_string(),
+					icon: None,
+					text,
+					short_text: None,
+					tooltip: None,
+					urgent: true,
+					classes: &[],
+					value: None,
+					is_percentage: false,
+					color: None,
+					background: None,
+					align: Align::default(),
+					on_click: None
+				});
+			}
 
-		if output != last_output {
-			B::output(&output);
-			last_output = output;
+			blocks.extend(modules.iter().enumerate()
+				.filter_map(|(i, module)| module.output.as_ref().map(|output| Block {
+					name: &module.config.implementation.name,
+					instance: module.config.id.clone().unwrap_or_else(|| i.to_string()),
+					icon: module.config.icon.as_deref(),
+					text: &output.full_text,
+					short_text: output.short_text.as_deref(),
+					tooltip: output.tooltip.as_deref(),
+					urgent: output.urgent,
+					classes: &output.classes,
+					value: output.value,
+					is_percentage: output.is_percentage,
+					color: block_color(&module.config, output),
+					background: block_background(&module.config, output),
+					align: module.config.align,
+					on_click: module.config.on_click.as_deref()
+				})));
+
+			backend.output(config, &blocks);
+			changed = false;
+			shown = true;
 		}
 
 		// Figure out how much we have to sleep for
 		
-		let mut least_sleep = Duration::MAX;
+		let now = Instant::now();
 
-		for counter in &counters {
-			if *counter < least_sleep {
-				least_sleep = *counter;
-			}
+		let mut sleep = modules.iter()
+			.map(|module| (module.started + module.counter).saturating_duration_since(now))
+			.min()
+			.unwrap_or(Duration::MAX);
+
+		if sleep > config.max_interval {
+			sleep = config.max_interval;
 		}
 
-		elapsed = start.elapsed();
+		// Finished modules wake us up by themselves, but the ones which hang don't
 
-		if least_sleep > elapsed {
-			let mut sleep = least_sleep - elapsed;
+		if let Some(deadline) = modules.iter().filter_map(|module| module.worker.deadline()).min() {
+			sleep = sleep.min(deadline.saturating_duration_since(now));
+		}
 
-			if sleep > config.max_interval {
-				sleep = config.max_interval;
-			}
+		if let Some(reload_in) = reload_in {
+			sleep = sleep.min(reload_in);
+		}
 
-			if params.verbose {
-				eprintln!("Going to sleep for {:?}.", sleep);
-			}
+		if params.verbose && !sleep.is_zero() {
+			eprintln!("Going to sleep for {:?}.", sleep);
+		}
+
+		click = backend.wait(sleep);
+	}
+}
+
+// Urgency beats the threshold rules, which beat the static colors from the config,
+// which in turn beat the module's own hints.
+
+fn matching_rule<'a>(config: &'a ModuleConfig, output: &modules::ModuleOutput) -> Option<&'a config::ColorRule> {
+	let value = output.value?;
+
+	config.colors.iter().find(|rule| rule.matches(value))
+}
+
+fn block_color(config: &ModuleConfig, output: &modules::ModuleOutput) -> Option<Color> {
+	output.urgent.then_some(config.urgent_color).flatten()
+		.or(matching_rule(config, output).and_then(|rule| rule.color))
+		.or(config.color)
+		.or(output.color)
+}
+
+fn block_background(config: &ModuleConfig, output: &modules::ModuleOutput) -> Option<Color> {
+	matching_rule(config, output).and_then(|rule| rule.background)
+		.or(config.background)
+		.or(output.background)
+}
+
+fn handle_click(modules: &mut [modules::ModuleRuntime], interrupts: &mut [bool], event: modules::ClickEvent, params: &args::AppParams) {
+	// Modules without an `id` are identified by their position
+
+	let found = modules.iter().position(|module| module.config.id.as_ref() == Some(&event.instance))
+		.or_else(|| event.instance.parse::<usize>().ok().filter(|i| *i < modules.len() && modules[*i].config.id.is_none()));
 
-			std::thread::sleep(sleep);
+	let Some(i) = found else {
+		if params.verbose {
+			eprintln!("Received click on unknown instance \"{}\".", event.instance);
 		}
+
+		return
+	};
+
+	if params.verbose {
+		eprintln!("Received click (button {}, modifiers {:?}, at {}x{}) on module {}.",
+			event.button, event.modifiers, event.x, event.y, modules[i].config.label());
 	}
+
+	// A user-specified command takes precedence over the module's own click handler
+
+	match &modules[i].config.on_click {
+		Some(cmd) => if let Err(err) = run_click_command(cmd, &event) {
+			eprintln!("Click handler of module {} failed: {}", modules[i].config.label(), err);
+		},
+		None => modules[i].worker.click(event)
+	}
+
+	// Run the module immediately, so that the change shows up on the bar
+
+	interrupts[i] = true;
 }
 
+
+fn run_click_command(cmd: &str, event: &modules::ClickEvent) -> Result<(), String> {
+	let mut child = std::process::Command::new("sh")
+		.arg("-c")
+		.arg(cmd)
+		.env("BUTTON", event.button.to_string())
+		.stdin(std::process::Stdio::null())
+		.stdout(std::process::Stdio::null())
+		.spawn()
+		.map_err(|e| format!("Error running `{}`: {}", cmd, e))?;
+
+	// Reap the process in the background, so that the bar does not block on it
+
+	std::thread::spawn(move || child.wait());
+
+	Ok(())
+}
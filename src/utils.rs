@@ -5,7 +5,7 @@ pub fn read_string(path: &str) -> Result<String, String> {
 	// Yes, this is just a fancy wrapper function.
 	
 	std::fs::read_to_string(path)
-		.map_err(|e| format!("File read error: {}", e))
+		.map_err(|e| format!("Error reading {}: {}", path, e))
 }
 
 pub fn read_line(path: &str) -> Result<String, String> {
@@ -44,3 +44,59 @@ pub fn format_duration(fmt: &str, dur: f64) -> Result<Option<String>, String> {
 	})
 }
 
+
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+	// Supports `*` (any number of characters) and `?` (exactly one character)
+
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+
+	let (mut p, mut n) = (0, 0);
+	let mut backtrack = None;
+
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+			p += 1;
+			n += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			backtrack = Some((p, n));
+			p += 1;
+		} else if let Some((star, matched)) = backtrack {
+			// Let the last `*` swallow one more character
+			backtrack = Some((star, matched + 1));
+			p = star + 1;
+			n = matched + 1;
+		} else {
+			return false
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn line_of(contents: &str, span: Option<std::ops::Range<usize>>) -> Option<usize> {
+	let span = span?;
+
+	Some(contents.as_bytes()[..span.start.min(contents.len())].iter().filter(|c| **c == b'\n').count() + 1)
+}
+
+// Levenshtein distance, for suggesting fixes to typos
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+
+	let mut row: Vec<usize> = (0..=b.len()).collect();
+
+	for (i, ca) in a.chars().enumerate() {
+		let mut prev = row[0];
+		row[0] = i + 1;
+
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = prev + (ca != *cb) as usize;
+
+			prev = row[j + 1];
+			row[j + 1] = substitution.min(prev + 1).min(row[j] + 1);
+		}
+	}
+
+	row[b.len()]
+}
@@ -0,0 +1,81 @@
+use std::os::fd::RawFd;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// A self-pipe which lets the module threads (and signal handlers) interrupt the
+// scheduler while it is sleeping in `Backend::wait`.
+
+struct Pipe {
+	read: RawFd,
+	write: RawFd
+}
+
+static PIPE: OnceLock<Pipe> = OnceLock::new();
+static SIGNALS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+fn pipe() -> &'static Pipe {
+	PIPE.get_or_init(|| {
+		let mut fds = [0; 2];
+
+		if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+			panic!("Could not create the wakeup pipe: {}", std::io::Error::last_os_error());
+		}
+
+		Pipe { read: fds[0], write: fds[1] }
+	})
+}
+
+pub fn fd() -> RawFd {
+	pipe().read
+}
+
+pub fn wake() {
+	// If the pipe is full, the scheduler is going to wake up anyway
+
+	unsafe {
+		libc::write(pipe().write, &1u8 as *const u8 as *const libc::c_void, 1);
+	}
+}
+
+// Called by the scheduler before it looks for work, so that only new wakeups count
+pub fn drain() {
+	let mut buf = [0u8; 64];
+
+	while unsafe { libc::read(pipe().read, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+}
+
+// Also wake up when the given signal arrives (the signal itself is still delivered to
+// `signal_hook::iterator::Signals`)
+pub fn watch_signal(signal: i32) -> Result<(), String> {
+	let mut signals = SIGNALS.lock().unwrap();
+
+	if signals.contains(&signal) {
+		return Ok(())
+	}
+
+	signal_hook::low_level::pipe::register_raw(signal, pipe().write)
+		.map_err(|e| format!("Error registering signal {}: {}", signal, e))?;
+
+	signals.push(signal);
+
+	Ok(())
+}
+
+// Sleeps until the timeout expires, someone calls `wake()`, or `other` becomes readable.
+// Returns whether `other` is readable.
+pub fn poll(other: Option<RawFd>, timeout: Duration) -> bool {
+	let mut fds = vec![libc::pollfd { fd: fd(), events: libc::POLLIN, revents: 0 }];
+
+	if let Some(other) = other {
+		fds.push(libc::pollfd { fd: other, events: libc::POLLIN, revents: 0 });
+	}
+
+	// Round up, so that we don't wake up just before the timeout and spin
+	let timeout = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+
+	if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } <= 0 {
+		return false
+	}
+
+	fds.get(1).is_some_and(|other| other.revents != 0)
+}
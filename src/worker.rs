@@ -0,0 +1,198 @@
+use crate::modules::{ClickEvent, ModuleInitFun, ModuleOutput, WakeSource};
+use crate::waker;
+
+use toml::Table;
+
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+// Every module lives on its own thread, so that a slow (or hung) module cannot
+// hold up the rest of the bar. Some modules (e.g. the PulseAudio ones) cannot be
+// moved between threads, so they are initialized on that thread as well.
+
+pub type RunResult = Result<Option<ModuleOutput>, String>;
+
+enum Request {
+	Run(Duration),
+	Click(ClickEvent)
+}
+
+pub struct Worker {
+	requests: Sender<Request>,
+	results: Receiver<RunResult>,
+	timeout: Duration,
+	running_since: Option<Instant>,
+	timed_out: bool,
+	changed: Arc<AtomicBool>
+}
+
+// Watchers check this often whether their module is still around
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn watch(source: WakeSource, changed: Weak<AtomicBool>) {
+	loop {
+		let event = match &source {
+			WakeSource::Fd(fd) => {
+				let mut pollfd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+
+				if unsafe { libc::poll(&mut pollfd, 1, WATCH_INTERVAL.as_millis() as i32) } < 0 {
+					let err = std::io::Error::last_os_error();
+
+					// Signals (e.g. for `unix_signal`) may land on this thread as well
+					if err.kind() == std::io::ErrorKind::Interrupted {
+						continue
+					}
+
+					eprintln!("Error watching for module updates: {}", err);
+					break
+				}
+
+				if pollfd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 {
+					break
+				}
+
+				let readable = pollfd.revents & libc::POLLIN != 0;
+
+				if readable {
+					let mut buf = [0u8; 4096];
+
+					unsafe {
+						libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+					}
+				}
+
+				readable
+			},
+			WakeSource::Channel(rx) => match rx.recv_timeout(WATCH_INTERVAL) {
+				Ok(()) => true,
+				Err(RecvTimeoutError::Timeout) => false,
+				Err(RecvTimeoutError::Disconnected) => break
+			}
+		};
+
+		// The worker is gone (e.g. after a config reload)
+		let Some(changed) = changed.upgrade() else { break };
+
+		if event {
+			changed.store(true, Ordering::Relaxed);
+			waker::wake();
+		}
+	}
+}
+
+impl Worker {
+	pub fn spawn(name: &str, init: ModuleInitFun, config: Table, timeout: Duration) -> Result<Self, String> {
+		let (init_tx, init_rx) = mpsc::channel();
+		let (requests, request_rx) = mpsc::channel();
+		let (result_tx, results) = mpsc::channel();
+
+		let thread_name = name.to_string();
+
+		let changed = Arc::new(AtomicBool::new(false));
+		let watched = Arc::downgrade(&changed);
+
+		std::thread::Builder::new()
+			.name(name.to_string())
+			.spawn(move || {
+				let mut module = match init(config) {
+					Ok(module) => module,
+					Err(err) => {
+						let _ = init_tx.send(Err(err));
+						return
+					}
+				};
+
+				let _ = init_tx.send(Ok(()));
+
+				if let Some(source) = module.watch() {
+					std::thread::spawn(move || watch(source, watched));
+				}
+
+				// Once the worker gets dropped (e.g. on config reload), the loop ends
+
+				for request in request_rx {
+					match request {
+						Request::Run(ts) => {
+							if result_tx.send(module.run(ts)).is_err() { break }
+							waker::wake();
+						},
+						Request::Click(event) => if let Err(err) = module.click(&event) {
+							eprintln!("Click handler of module {} failed: {}", thread_name, err);
+						}
+					}
+				}
+			})
+			.map_err(|e| format!("Error spawning thread: {}", e))?;
+
+		match init_rx.recv_timeout(timeout) {
+			Ok(Ok(())) => Ok(Worker {
+				requests,
+				results,
+				timeout,
+				running_since: None,
+				timed_out: false,
+				changed
+			}),
+			Ok(Err(err)) => Err(err),
+			Err(RecvTimeoutError::Timeout) => Err(format!("Initialization timed out after {:?}", timeout)),
+			Err(RecvTimeoutError::Disconnected) => Err("Module crashed during initialization".to_string())
+		}
+	}
+
+	pub fn is_running(&self) -> bool {
+		self.running_since.is_some()
+	}
+
+	// Running, but not timed out yet, i.e. the scheduler should keep an eye on it
+	pub fn is_pending(&self) -> bool {
+		self.is_running() && !self.timed_out
+	}
+
+	// When the current run is going to time out
+	pub fn deadline(&self) -> Option<Instant> {
+		self.running_since.filter(|_| !self.timed_out).map(|since| since + self.timeout)
+	}
+
+	// Whether the module's wake source has fired since the last call
+	pub fn take_changed(&self) -> bool {
+		self.changed.swap(false, Ordering::Relaxed)
+	}
+
+	pub fn run(&mut self, ts: Duration) {
+		// If the thread has died, the error will be picked up by `poll`
+		let _ = self.requests.send(Request::Run(ts));
+
+		self.running_since = Some(Instant::now());
+	}
+
+	pub fn click(&self, event: ClickEvent) {
+		let _ = self.requests.send(Request::Click(event));
+	}
+
+	// Returns the result of the last run once it finishes, or an error once it takes too long
+	pub fn poll(&mut self) -> Option<RunResult> {
+		let since = self.running_since?;
+
+		match self.results.try_recv() {
+			Ok(result) => {
+				(self.running_since, self.timed_out) = (None, false);
+				Some(result)
+			},
+			Err(TryRecvError::Empty) => {
+				if self.timed_out || since.elapsed() < self.timeout {
+					return None
+				}
+
+				self.timed_out = true;
+				Some(Err(format!("Timed out after {:?}", self.timeout)))
+			},
+			Err(TryRecvError::Disconnected) => {
+				(self.running_since, self.timed_out) = (None, false);
+				Some(Err("Module crashed".to_string()))
+			}
+		}
+	}
+}